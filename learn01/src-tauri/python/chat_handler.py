@@ -1,22 +1,72 @@
 import sys
 import json
-from openai import OpenAI
+import time
+from openai import BadRequestError, NotFoundError, OpenAI
 
+BASE_URL = "http://localhost:8081/v1"
+MODEL = "gpt-oss-20b"
 
-def process_message(message):
+# Models pinned by the app; this handler serves them from the upstream API,
+# so "loading" one checks that the upstream actually has it
+LOADED_MODELS = set()
+
+
+def build_messages(message, history=()):
+    return [
+        *({"role": m["role"], "content": m["content"]} for m in history),
+        {"role": "user", "content": message},
+    ]
+
+
+def process_message(message, history=(), seed=None):
     try:
-        client = OpenAI(base_url="http://localhost:8081/v1", api_key="")
+        client = OpenAI(base_url=BASE_URL, api_key="")
+        params = {
+            "model": MODEL,
+            "messages": build_messages(message, history),
+            "stream": True,
+            "stream_options": {"include_usage": True},
+        }
+        if seed is not None:
+            params["seed"] = seed
 
-        stream = client.chat.completions.create(
-            model="gpt-oss-20b",
-            messages=[{"role": "user", "content": message}],
-            stream=True,
-        )
+        seed_ignored = False
+        try:
+            stream = client.chat.completions.create(**params)
+        except BadRequestError as e:
+            # Some servers reject the parameter outright; answer anyway
+            if seed is None or "seed" not in str(e):
+                raise
+            del params["seed"]
+            stream = client.chat.completions.create(**params)
+            seed_ignored = True
 
         # return {"success": True, "message": response.choices[0].message.content}
 
         # Stream each chunk as it arrives
         for chunk in stream:
+            # With include_usage the last chunk carries usage and no choices
+            if chunk.usage is not None:
+                usage_data = {
+                    "type": "usage",
+                    "usage": {
+                        "prompt_tokens": chunk.usage.prompt_tokens,
+                        "completion_tokens": chunk.usage.completion_tokens,
+                    },
+                }
+                print(json.dumps(usage_data))
+                sys.stdout.flush()
+            if not chunk.choices:
+                continue
+
+            delta = chunk.choices[0].delta
+
+            # Reasoning models send their thinking separately from the answer
+            reasoning = getattr(delta, "reasoning_content", None)
+            if reasoning:
+                print(json.dumps({"type": "reasoning", "content": reasoning}))
+                sys.stdout.flush()
+
             if chunk.choices[0].delta.content is not None:
                 chunk_data = {
                     "type": "chunk",
@@ -25,7 +75,10 @@ def process_message(message):
                 print(json.dumps(chunk_data))
                 sys.stdout.flush()  # Ensure immediate output
 
-        print(json.dumps({"type": "complete", "success": True}))
+        complete = {"type": "complete", "success": True}
+        if seed_ignored:
+            complete["seed_ignored"] = True
+        print(json.dumps(complete))
         sys.stdout.flush()
     except Exception as e:
         error_data = {"type": "error", "success": False, "error": str(e)}
@@ -33,10 +86,91 @@ def process_message(message):
         sys.stdout.flush()
 
 
+def check_connectivity():
+    """Ping the upstream API and report whether it answered, and how fast."""
+    report = {"type": "connectivity", "upstream": BASE_URL}
+    started = time.monotonic()
+    try:
+        client = OpenAI(base_url=BASE_URL, api_key="", timeout=10.0, max_retries=0)
+        client.models.list()
+        report["reachable"] = True
+        report["latency_ms"] = round((time.monotonic() - started) * 1000)
+    except Exception as e:
+        report["reachable"] = False
+        report["error"] = str(e)
+    print(json.dumps(report))
+    sys.stdout.flush()
+
+
+def control_model(request):
+    model_id = request.get("model_id")
+    if request["type"] == "unload_model":
+        LOADED_MODELS.discard(model_id)
+        return {"success": True, "loaded_models": sorted(LOADED_MODELS)}
+
+    try:
+        OpenAI(base_url=BASE_URL, api_key="").models.retrieve(model_id)
+    except MemoryError as e:
+        return {"success": False, "reason": "out_of_memory", "error": str(e)}
+    except NotFoundError as e:
+        return {"success": False, "reason": "missing_weights", "error": str(e)}
+    except Exception as e:
+        return {"success": False, "reason": "other", "error": str(e)}
+    LOADED_MODELS.add(model_id)
+    return {"success": True, "loaded_models": sorted(LOADED_MODELS)}
+
+
+def handle_worker_request(request):
+    if request.get("type") in ("load_model", "unload_model"):
+        return control_model(request)
+    if request.get("type") == "chat" and request.get("message"):
+        client = OpenAI(base_url=BASE_URL, api_key="")
+        completion = client.chat.completions.create(
+            model=MODEL,
+            messages=build_messages(request["message"], request.get("history", [])),
+        )
+        return {"success": True, "message": completion.choices[0].message.content}
+    return {"success": False, "error": f"Unsupported request: {request.get('type')}"}
+
+
+def run_worker():
+    """Answer one JSON request line with one JSON response line until stdin closes."""
+    for line in sys.stdin:
+        if not line.strip():
+            continue
+        try:
+            response = handle_worker_request(json.loads(line))
+        except Exception as e:
+            response = {"success": False, "error": str(e)}
+        print(json.dumps(response))
+        sys.stdout.flush()
+
+
+def read_request():
+    """Read the JSON request the app writes as the first line on stdin."""
+    line = sys.stdin.readline()
+    if not line.strip():
+        return None
+    return json.loads(line)
+
+
 if __name__ == "__main__":
+    if sys.argv[1:] == ["--worker"]:
+        run_worker()
+        sys.exit(0)
+
+    # A message on the command line is handy for running the script by hand
     if len(sys.argv) > 1:
-        message = sys.argv[1]
-        result = process_message(message)
+        request = {"type": "chat", "message": sys.argv[1]}
+    else:
+        request = read_request()
+
+    if request and request.get("type") == "connectivity_check":
+        check_connectivity()
+    elif request and request.get("message"):
+        result = process_message(
+            request["message"], request.get("history", []), request.get("seed")
+        )
     else:
         print(
             json.dumps(
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use crate::StreamChunk;
+
+/// Removes ANSI escape sequences from a stream of text, including ones
+/// split across pieces.
+#[derive(Default)]
+pub struct AnsiStripper {
+    state: State,
+}
+
+#[derive(Default, Clone, Copy)]
+enum State {
+    #[default]
+    Text,
+    /// Just after ESC.
+    Escape,
+    /// Inside a control sequence, `ESC [` up to its final byte.
+    Csi,
+    /// Inside an operating system command, `ESC ]` up to BEL or `ESC \`.
+    Osc,
+    /// ESC seen inside an operating system command.
+    OscEscape,
+}
+
+impl AnsiStripper {
+    /// The text with escape sequences removed. A sequence left unfinished
+    /// is dropped from the next piece too.
+    pub fn push(&mut self, text: &str) -> String {
+        let mut clean = String::with_capacity(text.len());
+        for c in text.chars() {
+            self.state = match (self.state, c) {
+                (State::Text, '\x1b') => State::Escape,
+                (State::Text, '\u{9b}') => State::Csi,
+                (State::Text, c) => {
+                    clean.push(c);
+                    State::Text
+                }
+                (State::Escape, '[') => State::Csi,
+                (State::Escape, ']') => State::Osc,
+                // Two-character sequences such as `ESC 7`
+                (State::Escape, _) => State::Text,
+                (State::Csi, '\x40'..='\x7e') => State::Text,
+                (State::Csi, _) => State::Csi,
+                (State::Osc, '\x07') => State::Text,
+                (State::Osc, '\x1b') => State::OscEscape,
+                (State::Osc, _) => State::Osc,
+                (State::OscEscape, '\\') => State::Text,
+                (State::OscEscape, _) => State::Osc,
+            };
+        }
+        clean
+    }
+}
+
+/// Strips escape sequences from chunk content, keeping separate state for
+/// each chunk type so a sequence split between two content chunks is still
+/// recognized.
+#[derive(Default)]
+pub struct AnsiFilter {
+    strippers: HashMap<String, AnsiStripper>,
+}
+
+impl AnsiFilter {
+    pub fn chunk(&mut self, chunk: &mut StreamChunk) {
+        let Some(content) = &mut chunk.content else {
+            return;
+        };
+        let stripper = self.strippers.entry(chunk.chunk_type.clone()).or_default();
+        *content = stripper.push(content);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_csi_split_across_pieces_is_stripped() {
+        let mut stripper = AnsiStripper::default();
+        assert_eq!(stripper.push("\x1b[3"), "");
+        assert_eq!(stripper.push("1mred"), "red");
+    }
+
+    #[test]
+    fn an_osc_split_inside_its_terminator_is_stripped() {
+        let mut stripper = AnsiStripper::default();
+        assert_eq!(stripper.push("a\x1b]0;title\x1b"), "a");
+        assert_eq!(stripper.push("\\b"), "b");
+    }
+
+    #[test]
+    fn a_single_character_csi_is_stripped() {
+        let mut stripper = AnsiStripper::default();
+        assert_eq!(stripper.push("\u{9b}1mbold"), "bold");
+    }
+}
@@ -0,0 +1,162 @@
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use tauri::State;
+use tokio::io::BufReader;
+use tokio::process::{Child, ChildStdin};
+use tokio_util::io::StreamReader;
+
+use crate::config::ConfigState;
+use crate::error::CommandError;
+use crate::process::{self, HandlerOutput};
+use crate::python;
+use crate::request::PythonRequest;
+use crate::StreamOptions;
+
+/// Where streamed chat requests are sent.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Backend {
+    /// The local Python handler.
+    #[default]
+    Python,
+    /// A server that takes the handler's request as a POST body and answers
+    /// with the same chunks, framed as configured by `parse_mode`.
+    Http { url: String },
+}
+
+/// A chat request in flight: its streamed reply, plus the handler process
+/// behind it when there is one.
+pub struct Connection {
+    pub output: HandlerOutput,
+    pub child: Option<Child>,
+    /// Open for interactive streams, so tool results can follow.
+    pub stdin: Option<ChildStdin>,
+}
+
+/// Something that can answer a chat request with a stream of chunk lines.
+pub trait ChatBackend {
+    async fn open(&self, request: &PythonRequest) -> Result<Connection, CommandError>;
+}
+
+/// Runs the handler script once per request.
+pub struct PythonBackend<'a> {
+    pub app: &'a tauri::AppHandle,
+    pub config: &'a crate::PythonConfig,
+    pub options: &'a StreamOptions,
+    pub trace_id: &'a str,
+}
+
+impl ChatBackend for PythonBackend<'_> {
+    async fn open(&self, request: &PythonRequest) -> Result<Connection, CommandError> {
+        let mut command = python::handler_command(self.app, self.config, self.trace_id)?;
+        // Out of reach of signals sent to the app's group, e.g. Ctrl-C in the
+        // terminal running the dev server
+        #[cfg(unix)]
+        if self.options.detached {
+            command.process_group(0);
+        }
+
+        #[cfg(all(unix, feature = "pty"))]
+        let pty = if self.options.use_pty {
+            let (pty, device) = crate::pty::open()?;
+            command.stdout(device);
+            Some(pty)
+        } else {
+            None
+        };
+        #[cfg(not(all(unix, feature = "pty")))]
+        if self.options.use_pty {
+            tracing::warn!("use_pty needs the pty feature on Unix; falling back to pipes");
+        }
+
+        let mut child = python::spawn(self.app, self.config, &mut command)
+            .map_err(|e| format!("Failed to execute python: {}", e))?;
+        // The command holds a copy of the terminal device, which must be closed
+        // for the output to reach EOF
+        drop(command);
+        tracing::info!(pid = child.id(), "spawned handler");
+
+        // Deliver the request. Interactive streams keep stdin open for tool
+        // results; otherwise close it so the handler sees EOF
+        let stdin = if self.options.interactive {
+            Some(process::write_request_open(&mut child, &request.to_line()?).await?)
+        } else {
+            process::write_request(&mut child, &request.to_line()?).await?;
+            None
+        };
+
+        #[cfg(all(unix, feature = "pty"))]
+        let pty_output = pty.map(HandlerOutput::Pty);
+        #[cfg(not(all(unix, feature = "pty")))]
+        let pty_output = None;
+
+        let output = match pty_output {
+            Some(output) => output,
+            None => {
+                // Get stdout handle
+                let stdout = child
+                    .stdout
+                    .take()
+                    .ok_or_else(|| "Failed to capture stdout".to_string())?;
+
+                //Create buffered reader for line-by-line reading
+                HandlerOutput::Pipe(BufReader::new(stdout))
+            }
+        };
+
+        Ok(Connection {
+            output,
+            child: Some(child),
+            stdin,
+        })
+    }
+}
+
+/// POSTs the request to a remote server and streams back its response body.
+pub struct HttpBackend<'a> {
+    pub url: &'a str,
+}
+
+impl ChatBackend for HttpBackend<'_> {
+    async fn open(&self, request: &PythonRequest) -> Result<Connection, CommandError> {
+        let response = reqwest::Client::new()
+            .post(self.url)
+            .header("X-Trace-Id", &request.trace_id)
+            .json(request)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach {}: {}", self.url, e))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("{} answered {}: {}", self.url, status, body.trim()).into());
+        }
+        tracing::info!(url = self.url, "connected to remote backend");
+
+        let body = response.bytes_stream().map_err(std::io::Error::other);
+        Ok(Connection {
+            output: HandlerOutput::Http(Box::new(BufReader::new(StreamReader::new(body)))),
+            child: None,
+            stdin: None,
+        })
+    }
+}
+
+pub fn validate(backend: &Backend) -> Result<(), String> {
+    match backend {
+        Backend::Python => Ok(()),
+        Backend::Http { url } if url.starts_with("http://") || url.starts_with("https://") => {
+            Ok(())
+        }
+        Backend::Http { url } => Err(format!("Backend URL must be http(s): {}", url)),
+    }
+}
+
+/// Switch streamed chats between the local handler and a remote server.
+/// `send_to_python` always runs the local handler.
+#[tauri::command]
+pub fn set_backend(config: State<'_, ConfigState>, backend: Backend) -> Result<(), CommandError> {
+    validate(&backend)?;
+    config.update(|config| config.backend = backend);
+    Ok(())
+}
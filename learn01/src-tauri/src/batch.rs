@@ -0,0 +1,128 @@
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::StreamChunk;
+
+/// Coalesces content chunks that arrive within a time window so fast streams
+/// don't flood the webview with tiny events.
+pub struct ChunkBatcher {
+    interval: Duration,
+    pending: Option<StreamChunk>,
+    deadline: Option<Instant>,
+}
+
+impl ChunkBatcher {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            pending: None,
+            deadline: None,
+        }
+    }
+
+    /// Feed a chunk in. Returns the chunks that should be emitted right away,
+    /// in order. Content is held back until the window closes; anything else
+    /// flushes the pending batch and passes straight through.
+    pub fn push(&mut self, chunk: StreamChunk) -> Vec<StreamChunk> {
+        if !chunk.is_content() {
+            let mut ready: Vec<StreamChunk> = self.flush().into_iter().collect();
+            ready.push(chunk);
+            return ready;
+        }
+
+        match &mut self.pending {
+            Some(pending) => {
+                let content = chunk.content.unwrap_or_default();
+                pending
+                    .content
+                    .get_or_insert_with(String::new)
+                    .push_str(&content);
+            }
+            None => {
+                self.pending = Some(chunk);
+                self.deadline = Some(Instant::now() + self.interval);
+            }
+        }
+        Vec::new()
+    }
+
+    /// When the current batch is due to be flushed, if there is one.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Bytes of content in the pending batch.
+    pub fn pending_len(&self) -> usize {
+        self.pending
+            .as_ref()
+            .and_then(|chunk| chunk.content.as_ref())
+            .map_or(0, String::len)
+    }
+
+    /// Take the pending batch, if any.
+    pub fn flush(&mut self) -> Option<StreamChunk> {
+        self.deadline = None;
+        self.pending.take()
+    }
+}
+
+/// Sleep until `deadline`, or forever when there is none.
+pub async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content(text: &str) -> StreamChunk {
+        let mut chunk = StreamChunk::new("chunk");
+        chunk.content = Some(text.to_string());
+        chunk
+    }
+
+    fn summary(chunks: &[StreamChunk]) -> Vec<(String, Option<String>)> {
+        chunks
+            .iter()
+            .map(|chunk| (chunk.chunk_type.clone(), chunk.content.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn non_content_flushes_the_batch_ahead_of_itself() {
+        let mut batcher = ChunkBatcher::new(Duration::from_secs(60));
+        let mut emitted = Vec::new();
+        emitted.extend(batcher.push(content("a")));
+        emitted.extend(batcher.push(content("b")));
+        assert!(emitted.is_empty());
+
+        emitted.extend(batcher.push(StreamChunk::new("warning")));
+        emitted.extend(batcher.push(content("c")));
+        emitted.extend(batcher.flush());
+        assert_eq!(
+            summary(&emitted),
+            [
+                ("chunk".to_string(), Some("ab".to_string())),
+                ("warning".to_string(), None),
+                ("chunk".to_string(), Some("c".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn flush_releases_the_pending_batch() {
+        let mut batcher = ChunkBatcher::new(Duration::from_secs(60));
+        batcher.push(content("held"));
+        assert_eq!(batcher.pending_len(), 4);
+        assert!(batcher.deadline().is_some());
+
+        let flushed = batcher.flush().unwrap();
+        assert_eq!(flushed.content.as_deref(), Some("held"));
+        assert_eq!(batcher.pending_len(), 0);
+        assert!(batcher.deadline().is_none());
+        assert!(batcher.flush().is_none());
+    }
+}
@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+const OPEN: &str = "[[cite:";
+const CLOSE: &str = "]]";
+
+/// Longer "ids" are taken for ordinary text that happens to contain `OPEN`.
+const MAX_ID_LEN: usize = 128;
+
+fn is_doc_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_ID_LEN && !id.contains('[')
+}
+
+/// A cited document and the number it's referred to by in the content.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Citation {
+    pub number: usize,
+    pub doc_id: String,
+}
+
+/// Replaces `[[cite:doc_id]]` markers in streamed text with reference
+/// numbers like `[1]`, including markers split across chunk boundaries.
+/// Each document keeps the number it was first cited with.
+#[derive(Default)]
+pub struct CitationExtractor {
+    /// Tail of the text seen so far that could still be a marker, withheld
+    /// until it's known.
+    held: String,
+    /// Documents in order of first citation; a document's number is its
+    /// position plus one.
+    citations: Vec<Citation>,
+}
+
+impl CitationExtractor {
+    /// Feed the next piece of content. Returns the text that is safe to emit
+    /// and the documents cited for the first time in it.
+    pub fn push(&mut self, text: &str) -> (String, Vec<Citation>) {
+        let mut buffer = std::mem::take(&mut self.held);
+        buffer.push_str(text);
+
+        let mut clean = String::with_capacity(buffer.len());
+        let mut new = Vec::new();
+        let mut rest = 0;
+        while let Some(start) = buffer[rest..].find(OPEN).map(|index| rest + index) {
+            clean.push_str(&buffer[rest..start]);
+            let id_start = start + OPEN.len();
+            match buffer[id_start..].find(CLOSE) {
+                Some(len) if is_doc_id(&buffer[id_start..id_start + len]) => {
+                    let number = self.number(&buffer[id_start..id_start + len], &mut new);
+                    let _ = write!(clean, "[{}]", number);
+                    rest = id_start + len + CLOSE.len();
+                }
+                None if buffer.len() - id_start <= MAX_ID_LEN => {
+                    self.held = buffer[start..].to_string();
+                    return (clean, new);
+                }
+                // Not a marker after all
+                _ => {
+                    clean.push_str(OPEN);
+                    rest = id_start;
+                }
+            }
+        }
+
+        // Hold back an ending that could be the start of the next marker
+        let tail = &buffer[rest..];
+        let partial = (1..OPEN.len())
+            .rev()
+            .find(|&len| tail.ends_with(&OPEN[..len]))
+            .unwrap_or(0);
+        let (safe, held) = tail.split_at(tail.len() - partial);
+        clean.push_str(safe);
+        self.held = held.to_string();
+        (clean, new)
+    }
+
+    /// Release the withheld text as is; an unfinished marker is left alone.
+    pub fn finish(&mut self) -> String {
+        std::mem::take(&mut self.held)
+    }
+
+    /// Every document cited so far, by number.
+    pub fn citations(&self) -> &[Citation] {
+        &self.citations
+    }
+
+    fn number(&mut self, doc_id: &str, new: &mut Vec<Citation>) -> usize {
+        if let Some(citation) = self.citations.iter().find(|c| c.doc_id == doc_id) {
+            return citation.number;
+        }
+        let citation = Citation {
+            number: self.citations.len() + 1,
+            doc_id: doc_id.to_string(),
+        };
+        new.push(citation.clone());
+        self.citations.push(citation);
+        self.citations.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc_ids(citations: &[Citation]) -> Vec<(usize, &str)> {
+        citations
+            .iter()
+            .map(|citation| (citation.number, citation.doc_id.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn a_marker_split_across_pieces_is_replaced() {
+        let mut extractor = CitationExtractor::default();
+        let (text, cited) = extractor.push("see [[ci");
+        assert_eq!(text, "see ");
+        assert!(cited.is_empty());
+        let (text, cited) = extractor.push("te:doc1]] here");
+        assert_eq!(text, "[1] here");
+        assert_eq!(doc_ids(&cited), [(1, "doc1")]);
+        assert_eq!(extractor.finish(), "");
+    }
+
+    #[test]
+    fn a_document_cited_again_keeps_its_number() {
+        let mut extractor = CitationExtractor::default();
+        let (text, cited) = extractor.push("[[cite:a]] [[cite:b]] [[cite:a]]");
+        assert_eq!(text, "[1] [2] [1]");
+        assert_eq!(doc_ids(&cited), [(1, "a"), (2, "b")]);
+        let (text, cited) = extractor.push(" and [[cite:b]]");
+        assert_eq!(text, " and [2]");
+        assert!(cited.is_empty());
+        assert_eq!(doc_ids(extractor.citations()), [(1, "a"), (2, "b")]);
+    }
+
+    #[test]
+    fn invalid_ids_are_left_as_text() {
+        let mut extractor = CitationExtractor::default();
+        let (text, cited) = extractor.push("[[cite:]] [[cite:a[b]]");
+        assert_eq!(text, "[[cite:]] [[cite:a[b]]");
+        assert!(cited.is_empty());
+
+        let long = format!("[[cite:{}]]", "x".repeat(MAX_ID_LEN + 1));
+        let (text, cited) = extractor.push(&long);
+        assert_eq!(text, long);
+        assert!(cited.is_empty());
+    }
+
+    #[test]
+    fn an_unclosed_marker_is_released_once_too_long_for_an_id() {
+        let mut extractor = CitationExtractor::default();
+        let (text, _) = extractor.push("[[cite:");
+        assert_eq!(text, "");
+        let filler = "x".repeat(MAX_ID_LEN + 1);
+        let (text, _) = extractor.push(&filler);
+        assert_eq!(text, format!("[[cite:{}", filler));
+    }
+}
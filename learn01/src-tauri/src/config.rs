@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::backend::{self, Backend};
+use crate::detect;
+use crate::error::CommandError;
+use crate::parse::ParseMode;
+use crate::sandbox::Sandbox;
+
+/// User-adjustable settings for launching the Python handler.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PythonConfig {
+    /// Interpreter to launch the handler with. Falls back to the platform
+    /// default on PATH when unset.
+    pub interpreter: Option<PathBuf>,
+    /// Extra module search paths, prepended to any inherited `PYTHONPATH`.
+    pub python_path: Vec<PathBuf>,
+    /// Messages longer than this many characters are rejected before Python
+    /// is launched.
+    pub max_message_chars: Option<usize>,
+    /// Debugging aid: let the handler write straight to the app's terminal
+    /// instead of capturing its output. Only `send_to_python` supports this,
+    /// and it then returns no message.
+    pub inherit_stdio: bool,
+    /// Oldest acceptable interpreter version for detection, e.g. `"3.9"`.
+    pub min_python_version: Option<String>,
+    /// Apply Unicode NFC normalization to the text in every chunk and
+    /// response, so equal strings compare equal in the frontend. Escaping is
+    /// normalized regardless, since all output is re-serialized.
+    pub normalize_output: bool,
+    /// Stop a worker pool's processes after this many seconds without a
+    /// request; the next request starts them again.
+    pub idle_timeout_secs: Option<u64>,
+    /// How streamed handler output is framed; NDJSON by default.
+    pub parse_mode: ParseMode,
+    /// When `parse_mode` is NDJSON but a stream's first lines aren't JSON,
+    /// switch that stream to SSE or plain text, whichever they look like,
+    /// and say so with a `framing_switched` chunk.
+    pub detect_framing: bool,
+    /// Most chunk events a stream sends per second; content over the cap is
+    /// merged into the next event, and terminal chunks always go straight
+    /// out. Uncapped when unset.
+    pub max_emits_per_sec: Option<u32>,
+    /// Cancel streams that are running when the machine goes to sleep,
+    /// instead of letting them carry on after waking up. Where the OS
+    /// doesn't say it is about to sleep, they are cancelled on waking up.
+    pub cancel_streams_after_sleep: bool,
+    /// Restrict what handler processes may do; unrestricted when unset.
+    pub sandbox: Option<Sandbox>,
+    /// Where streamed chats go; the local handler unless switched with
+    /// `set_backend`.
+    pub backend: Backend,
+    /// Remove ANSI escape sequences, e.g. colors from handler logs, from
+    /// chunk content and stderr before emitting them. Recordings keep the
+    /// raw output.
+    pub strip_ansi: bool,
+    /// Text the message is embedded in before it's sent to the handler,
+    /// e.g. `"Answer concisely.\n\nUser: {message}\n\nAssistant:"`. Must
+    /// contain `{message}` exactly once. History keeps the bare message.
+    pub prompt_template: Option<String>,
+    /// With more streams than this running, they take turns reading their
+    /// handlers' output, a bounded amount each, so one busy stream can't
+    /// starve the rest. Streams read freely when unset.
+    pub fair_read_above: Option<usize>,
+}
+
+/// Where the message goes in a `prompt_template`.
+const MESSAGE_PLACEHOLDER: &str = "{message}";
+
+impl Default for PythonConfig {
+    fn default() -> Self {
+        Self {
+            interpreter: None,
+            python_path: Vec::new(),
+            max_message_chars: None,
+            inherit_stdio: false,
+            min_python_version: None,
+            normalize_output: false,
+            idle_timeout_secs: None,
+            parse_mode: ParseMode::default(),
+            detect_framing: false,
+            max_emits_per_sec: None,
+            cancel_streams_after_sleep: false,
+            sandbox: None,
+            backend: Backend::default(),
+            strip_ansi: true,
+            prompt_template: None,
+            fair_read_above: None,
+        }
+    }
+}
+
+impl PythonConfig {
+    /// Reject a message the handler is configured not to accept.
+    pub fn check_message(&self, message: &str) -> Result<(), CommandError> {
+        let Some(limit) = self.max_message_chars else {
+            return Ok(());
+        };
+        let actual = message.chars().count();
+        if actual > limit {
+            return Err(CommandError::MessageTooLong { limit, actual });
+        }
+        Ok(())
+    }
+
+    /// The message as the handler should receive it.
+    pub fn prompt(&self, message: &str) -> String {
+        match &self.prompt_template {
+            Some(template) => template.replacen(MESSAGE_PLACEHOLDER, message, 1),
+            None => message.to_string(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(min) = &self.min_python_version {
+            if detect::parse_version(min).is_none() {
+                return Err(format!("Invalid minimum Python version: {}", min));
+            }
+        }
+        if self.idle_timeout_secs == Some(0) {
+            return Err("Idle timeout must be at least 1 second".to_string());
+        }
+        validate_emit_rate_cap(self.max_emits_per_sec)?;
+        if let Some(template) = &self.prompt_template {
+            let placeholders = template.matches(MESSAGE_PLACEHOLDER).count();
+            if placeholders != 1 {
+                return Err(format!(
+                    "Prompt template must contain {} exactly once, found {}",
+                    MESSAGE_PLACEHOLDER, placeholders
+                ));
+            }
+        }
+        if let Some(sandbox) = &self.sandbox {
+            sandbox.validate()?;
+        }
+        backend::validate(&self.backend)?;
+        validate_python_path(&self.python_path)
+    }
+}
+
+fn validate_emit_rate_cap(max_emits_per_sec: Option<u32>) -> Result<(), String> {
+    match max_emits_per_sec {
+        Some(0) => Err("Emit rate cap must be at least 1 per second".to_string()),
+        _ => Ok(()),
+    }
+}
+
+fn validate_python_path(paths: &[PathBuf]) -> Result<(), String> {
+    match paths.iter().find(|path| !path.is_dir()) {
+        Some(missing) => Err(format!("Python path entry not found: {:?}", missing)),
+        None => Ok(()),
+    }
+}
+
+/// Managed state holding the active `PythonConfig`.
+#[derive(Default)]
+pub struct ConfigState(Mutex<PythonConfig>);
+
+impl ConfigState {
+    /// Snapshot of the current config.
+    pub fn get(&self) -> PythonConfig {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn update(&self, f: impl FnOnce(&mut PythonConfig)) {
+        f(&mut self.0.lock().unwrap());
+    }
+}
+
+#[tauri::command]
+pub fn get_python_config(config: tauri::State<'_, ConfigState>) -> PythonConfig {
+    config.get()
+}
+
+/// Replace the whole config at once.
+#[tauri::command]
+pub fn set_python_config(
+    config: tauri::State<'_, ConfigState>,
+    new_config: PythonConfig,
+) -> Result<(), CommandError> {
+    new_config.validate()?;
+    config.update(|config| *config = new_config);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_python_path(
+    config: tauri::State<'_, ConfigState>,
+    paths: Vec<PathBuf>,
+) -> Result<(), CommandError> {
+    validate_python_path(&paths)?;
+    config.update(|config| config.python_path = paths);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_emit_rate_cap(config: tauri::State<'_, ConfigState>) -> Option<u32> {
+    config.get().max_emits_per_sec
+}
+
+/// Cap the chunk events each stream sends per second; `None` lifts the cap.
+/// Applies to streams started afterwards.
+#[tauri::command]
+pub fn set_emit_rate_cap(
+    config: tauri::State<'_, ConfigState>,
+    max_emits_per_sec: Option<u32>,
+) -> Result<(), CommandError> {
+    validate_emit_rate_cap(max_emits_per_sec)?;
+    config.update(|config| config.max_emits_per_sec = max_emits_per_sec);
+    Ok(())
+}
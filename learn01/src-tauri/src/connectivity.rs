@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::{AppHandle, State};
+
+use crate::config::ConfigState;
+use crate::error::CommandError;
+use crate::process;
+use crate::python;
+use crate::request::{self, PythonRequest};
+
+/// Long enough for the handler's own upstream timeout to fire first.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Whether the handler could reach its upstream API.
+#[derive(Serialize, Deserialize)]
+pub struct ConnectivityReport {
+    reachable: bool,
+    /// Round trip of the ping, when it succeeded.
+    latency_ms: Option<u64>,
+    /// The URL the handler talks to, if it says.
+    upstream: Option<String>,
+    /// Why the upstream couldn't be reached.
+    error: Option<String>,
+}
+
+/// Have the handler ping its upstream. A report with `reachable: false`
+/// points to the network; an error means the handler itself is broken.
+#[tauri::command]
+pub async fn test_upstream_connectivity(
+    app: AppHandle,
+    config: State<'_, ConfigState>,
+) -> Result<ConnectivityReport, CommandError> {
+    let config = config.get();
+    let trace_id = request::trace_id(None);
+    let mut command = python::handler_command(&app, &config, &trace_id)?;
+    let mut child = python::spawn(&app, &config, &mut command)
+        .map_err(|e| format!("Failed to execute python: {}", e))?;
+
+    let request = PythonRequest::connectivity_check(&trace_id).to_line()?;
+    process::write_request(&mut child, &request).await?;
+
+    let output = tokio::time::timeout(CHECK_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| "Connectivity check timed out".to_string())?
+        .map_err(|e| format!("Failed to wait for python: {}", e))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let report = stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find(|value| value["type"] == "connectivity")
+        .and_then(|value| serde_json::from_value(value).ok());
+
+    match report {
+        Some(report) if output.status.success() => Ok(report),
+        _ => Err(CommandError::PythonError {
+            message: "The handler did not report connectivity".to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            exit_code: output.status.code(),
+        }),
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Manager, State};
+
+use crate::config::PythonConfig;
+use crate::error::CommandError;
+use crate::sink::ChunkSink;
+use crate::streams::StreamRegistry;
+use crate::{StreamChunk, StreamOptions};
+
+/// How long an ended stream can still be reattached, for a frontend that
+/// reloaded just as it ended.
+const ENDED_RETENTION: Duration = Duration::from_secs(10 * 60);
+
+/// A stream running independently of the window that started it. Every
+/// chunk is kept so a reloaded frontend can catch up with `reattach_stream`.
+pub struct DetachedStream(Mutex<Attachment>);
+
+struct Attachment {
+    sent: Vec<StreamChunk>,
+    /// Where live chunks go; dropped when delivery fails, e.g. because the
+    /// page went away.
+    listener: Option<ChunkSink>,
+    ended: Option<Instant>,
+}
+
+impl DetachedStream {
+    /// A stream with nothing sent yet, delivering live chunks to `listener`.
+    pub fn new(listener: Option<ChunkSink>) -> Self {
+        Self(Mutex::new(Attachment {
+            sent: Vec::new(),
+            listener,
+            ended: None,
+        }))
+    }
+
+    fn has_ended(&self) -> bool {
+        self.0.lock().unwrap().ended.is_some()
+    }
+
+    /// Whether the stream ended longer than `ENDED_RETENTION` ago.
+    fn has_expired(&self) -> bool {
+        self.0
+            .lock()
+            .unwrap()
+            .ended
+            .is_some_and(|ended| ended.elapsed() >= ENDED_RETENTION)
+    }
+
+    pub fn send(&self, chunk: &StreamChunk) {
+        let mut attachment = self.0.lock().unwrap();
+        attachment.sent.push(chunk.clone());
+        if let Some(listener) = &attachment.listener {
+            if let Err(e) = listener.send(chunk) {
+                tracing::info!("detached stream lost its listener: {}", e);
+                attachment.listener = None;
+            }
+        }
+    }
+
+    /// Replay everything sent so far to `listener`, then keep it for live
+    /// chunks. Holding the lock throughout keeps the two in order.
+    fn attach(&self, listener: ChunkSink) -> Result<(), String> {
+        let mut attachment = self.0.lock().unwrap();
+        for chunk in &attachment.sent {
+            listener.send(chunk)?;
+        }
+        attachment.listener = Some(listener);
+        Ok(())
+    }
+}
+
+/// Detached streams by session, most recent per session. Ended ones stay
+/// until they have been reattached once, or for `ENDED_RETENTION`.
+#[derive(Default)]
+pub struct DetachedStreams(Mutex<HashMap<String, Arc<DetachedStream>>>);
+
+impl DetachedStreams {
+    /// Sessions with a detached stream, sorted.
+    pub fn sessions(&self) -> Vec<String> {
+        let mut streams = self.0.lock().unwrap();
+        streams.retain(|_, stream| !stream.has_expired());
+        let mut sessions: Vec<String> = streams.keys().cloned().collect();
+        sessions.sort();
+        sessions
+    }
+
+    /// Make `stream` the one of `session_id` to reattach to.
+    fn start(&self, session_id: &str, stream: Arc<DetachedStream>) {
+        let mut streams = self.0.lock().unwrap();
+        streams.retain(|_, stream| !stream.has_expired());
+        streams.insert(session_id.to_string(), stream);
+    }
+
+    /// Note that `stream` has ended; it can still be reattached for a while.
+    fn ended(&self, stream: &DetachedStream) {
+        stream.0.lock().unwrap().ended = Some(Instant::now());
+    }
+
+    /// Replay the latest stream of `session_id` to `listener` and keep it
+    /// attached. A stream that has ended is forgotten once replayed.
+    fn reattach(&self, session_id: &str, listener: ChunkSink) -> Result<(), String> {
+        let mut streams = self.0.lock().unwrap();
+        streams.retain(|_, stream| !stream.has_expired());
+        let stream = streams
+            .get(session_id)
+            .ok_or_else(|| format!("No detached stream for session {:?}", session_id))?;
+        stream.attach(listener)?;
+        if stream.has_ended() {
+            streams.remove(session_id);
+        }
+        Ok(())
+    }
+
+    /// Forget every detached stream. Ones still running carry on, but can no
+    /// longer be reattached.
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+/// Start a stream that outlives the invoke and window that asked for it.
+/// Chunks go to `sink` until it fails, and are buffered for
+/// `reattach_stream` either way. The request is
+/// checked first, so a bad one fails the invoke instead of becoming a chunk.
+pub fn spawn(
+    app: AppHandle,
+    sink: ChunkSink,
+    config: PythonConfig,
+    message: String,
+    options: StreamOptions,
+) -> Result<(), CommandError> {
+    let session_id = options
+        .session_id
+        .clone()
+        .ok_or_else(|| "Detached streams need a session_id".to_string())?;
+    crate::check_stream(&config, &message, &options)?;
+    let stream = Arc::new(DetachedStream::new(Some(sink)));
+    app.state::<DetachedStreams>()
+        .start(&session_id, stream.clone());
+
+    tauri::async_runtime::spawn(async move {
+        let registry = app.state::<StreamRegistry>();
+        let sink = ChunkSink::Detached(stream.clone());
+        let result = crate::run_stream(&app, &sink, &registry, config, &message, &options).await;
+        // Nobody is waiting on the invoke, so failures become chunks
+        if let Err(e) = result {
+            let mut chunk = StreamChunk::new("error");
+            chunk.success = Some(false);
+            chunk.error = Some(e.to_string());
+            let _ = sink.send(&chunk);
+        }
+        app.state::<DetachedStreams>().ended(&stream);
+    });
+    Ok(())
+}
+
+/// Receive the latest detached stream of `session_id` through `on_chunk`:
+/// everything it has sent so far, then live chunks until it ends. A stream
+/// that has ended can be reattached once, within `ENDED_RETENTION`.
+#[tauri::command]
+pub fn reattach_stream(
+    streams: State<'_, DetachedStreams>,
+    session_id: String,
+    on_chunk: Channel<StreamChunk>,
+) -> Result<(), CommandError> {
+    streams.reattach(&session_id, ChunkSink::Channel(on_chunk))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listener() -> (Arc<DetachedStream>, ChunkSink) {
+        let stream = Arc::new(DetachedStream::new(None));
+        (stream.clone(), ChunkSink::Detached(stream))
+    }
+
+    fn sent(stream: &DetachedStream) -> Vec<String> {
+        let attachment = stream.0.lock().unwrap();
+        attachment
+            .sent
+            .iter()
+            .map(|chunk| chunk.chunk_type.clone())
+            .collect()
+    }
+
+    #[test]
+    fn an_ended_stream_can_be_reattached_once() {
+        let streams = DetachedStreams::default();
+        let stream = Arc::new(DetachedStream::new(None));
+        streams.start("session", stream.clone());
+        stream.send(&StreamChunk::new("chunk"));
+        stream.send(&StreamChunk::new("complete"));
+        streams.ended(&stream);
+        assert_eq!(streams.sessions(), ["session"]);
+
+        let (first, sink) = listener();
+        streams.reattach("session", sink).unwrap();
+        assert_eq!(sent(&first), ["chunk", "complete"]);
+
+        let (_, sink) = listener();
+        assert!(streams.reattach("session", sink).is_err());
+        assert!(streams.sessions().is_empty());
+    }
+
+    #[test]
+    fn a_running_stream_stays_after_reattaching() {
+        let streams = DetachedStreams::default();
+        let stream = Arc::new(DetachedStream::new(None));
+        streams.start("session", stream.clone());
+        stream.send(&StreamChunk::new("chunk"));
+
+        let (first, sink) = listener();
+        streams.reattach("session", sink).unwrap();
+        stream.send(&StreamChunk::new("complete"));
+        assert_eq!(sent(&first), ["chunk", "complete"]);
+        assert_eq!(streams.sessions(), ["session"]);
+    }
+}
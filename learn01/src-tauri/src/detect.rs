@@ -0,0 +1,139 @@
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::State;
+use tokio::process::Command as AsyncCommand;
+
+use crate::config::{ConfigState, PythonConfig};
+use crate::error::CommandError;
+
+/// Interpreters to probe, best first.
+const CANDIDATES: &[&str] = &[
+    "python3.13",
+    "python3.12",
+    "python3.11",
+    "python3.10",
+    "python3.9",
+    "python3.8",
+    "python3",
+    "python",
+];
+
+/// Prints the absolute interpreter path and its version on separate lines.
+const PROBE: &str = "import sys; print(sys.executable); print('%d.%d.%d' % sys.version_info[:3])";
+
+#[derive(Clone, Serialize)]
+pub struct DetectedPython {
+    pub path: PathBuf,
+    pub version: String,
+}
+
+/// Last detection result, with the minimum version it was checked against.
+#[derive(Default)]
+pub struct DetectionCache(Mutex<Option<(Option<String>, DetectedPython)>>);
+
+impl DetectionCache {
+    /// The previously detected interpreter, if detection has run.
+    pub fn get(&self) -> Option<DetectedPython> {
+        self.0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|(_, detected)| detected.clone())
+    }
+
+    pub fn clear(&self) {
+        *self.0.lock().unwrap() = None;
+    }
+}
+
+/// Parse `"3.11.4"` or `"3.9"` into comparable parts.
+pub fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.trim().split('.').map(|part| part.parse::<u32>());
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+    let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+    Some((major, minor, patch))
+}
+
+async fn probe(candidate: &str) -> Option<DetectedPython> {
+    let output = AsyncCommand::new(candidate)
+        .args(["-c", PROBE])
+        .kill_on_drop(true)
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    let path = PathBuf::from(lines.next()?.trim());
+    let version = lines.next()?.trim().to_string();
+    parse_version(&version)?;
+    Some(DetectedPython { path, version })
+}
+
+/// Probe the candidates in order and return the first that satisfies
+/// `min_python_version`, caching the result.
+pub async fn detect(
+    cache: &DetectionCache,
+    config: &PythonConfig,
+) -> Result<DetectedPython, CommandError> {
+    let min_version = config.min_python_version.clone();
+    if let Some((checked_min, detected)) = cache.0.lock().unwrap().as_ref() {
+        if *checked_min == min_version {
+            return Ok(detected.clone());
+        }
+    }
+
+    let found = search(config).await?;
+    *cache.0.lock().unwrap() = Some((min_version, found.clone()));
+    Ok(found)
+}
+
+/// Probe the candidates in order for the first that satisfies
+/// `min_version`, leaving the cache alone.
+pub async fn search(config: &PythonConfig) -> Result<DetectedPython, CommandError> {
+    let min_version = config.min_python_version.clone();
+    let required = match &min_version {
+        Some(min) => Some(
+            parse_version(min).ok_or_else(|| format!("Invalid minimum Python version: {}", min))?,
+        ),
+        None => None,
+    };
+
+    let mut too_old = Vec::new();
+    for candidate in CANDIDATES {
+        let Some(found) = probe(candidate).await else {
+            continue;
+        };
+        let meets_minimum = match (required, parse_version(&found.version)) {
+            (Some(required), Some(version)) => version >= required,
+            _ => true,
+        };
+        if meets_minimum {
+            return Ok(found);
+        }
+        too_old.push(format!("{} ({})", found.version, found.path.display()));
+    }
+
+    let message = match min_version {
+        Some(min) if !too_old.is_empty() => format!(
+            "Python {} or newer is required; found only {}",
+            min,
+            too_old.join(", ")
+        ),
+        _ => "No Python interpreter found on PATH".to_string(),
+    };
+    Err(message.into())
+}
+
+#[tauri::command]
+pub async fn detect_python(
+    cache: State<'_, DetectionCache>,
+    config: State<'_, ConfigState>,
+) -> Result<DetectedPython, CommandError> {
+    detect(&cache, &config.get()).await
+}
@@ -0,0 +1,84 @@
+use serde::Serialize;
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+use tokio::process::Command as AsyncCommand;
+
+use crate::config::PythonConfig;
+use crate::detect::{self, DetectionCache};
+use crate::python;
+
+/// Result of the Python subsystem self-test.
+#[derive(Clone, Serialize)]
+pub struct Diagnostics {
+    python_available: bool,
+    interpreter: PathBuf,
+    /// Why interpreter detection failed, when it ran and did.
+    detection_error: Option<String>,
+    python_version: Option<String>,
+    script_path: Option<PathBuf>,
+    script_found: bool,
+}
+
+impl Diagnostics {
+    pub fn is_healthy(&self) -> bool {
+        self.python_available && self.script_found
+    }
+}
+
+/// Check that the interpreter runs and that the handler script is in place.
+pub async fn self_test(app: &AppHandle, config: &PythonConfig) -> Diagnostics {
+    let detection_error = match config.interpreter {
+        Some(_) => None,
+        None => detect::detect(&app.state::<DetectionCache>(), config)
+            .await
+            .err()
+            .map(|e| e.to_string()),
+    };
+    run(python::interpreter(app, config), detection_error).await
+}
+
+/// `self_test` for a config that may not be the active one: detection
+/// neither uses nor updates the cache the active config relies on.
+pub async fn self_test_isolated(config: &PythonConfig) -> Diagnostics {
+    if let Some(interpreter) = &config.interpreter {
+        return run(interpreter.clone(), None).await;
+    }
+    match detect::search(config).await {
+        Ok(detected) => run(detected.path, None).await,
+        Err(e) => run(python::python_command().into(), Some(e.to_string())).await,
+    }
+}
+
+async fn run(interpreter: PathBuf, detection_error: Option<String>) -> Diagnostics {
+    let python_version = match AsyncCommand::new(&interpreter)
+        .arg("--version")
+        .kill_on_drop(true)
+        .output()
+        .await
+    {
+        // Older interpreters print the version to stderr
+        Ok(output) if output.status.success() => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let version = if stdout.trim().is_empty() {
+                stderr
+            } else {
+                stdout
+            };
+            Some(version.trim().to_string())
+        }
+        _ => None,
+    };
+
+    let script_path = python::handler_script().ok();
+    let script_found = script_path.as_ref().is_some_and(|path| path.exists());
+
+    Diagnostics {
+        python_available: python_version.is_some(),
+        interpreter,
+        detection_error,
+        python_version,
+        script_path,
+        script_found,
+    }
+}
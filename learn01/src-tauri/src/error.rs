@@ -0,0 +1,83 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Error returned by commands. Serialized as `{ "kind": ..., ...fields }` so
+/// the frontend can tell failures apart without parsing message text.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CommandError {
+    /// The message exceeds the configured `max_message_chars`.
+    MessageTooLong { limit: usize, actual: usize },
+    /// The Python handler itself failed, with whatever it wrote to stderr.
+    PythonError {
+        message: String,
+        stderr: String,
+        exit_code: Option<i32>,
+    },
+    /// A worker could not load or evict a model. `reason` is the worker's
+    /// classification, e.g. `out_of_memory` or `missing_weights`.
+    ModelLoadFailed {
+        model_id: String,
+        reason: String,
+        message: String,
+    },
+    /// Cancelled before it finished.
+    Cancelled,
+    /// The handler took too long in the given phase and was killed.
+    Timeout { phase: TimeoutPhase },
+    /// No tokenizer is known for the requested model.
+    TokenizerUnavailable { model: String },
+    /// Any other failure, described for display.
+    Failed { message: String },
+}
+
+/// Which wait ran out, for a `Timeout`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeoutPhase {
+    /// Nothing arrived between starting the handler and its first chunk,
+    /// typically a hang or a slow import rather than slow streaming.
+    FirstChunk,
+    /// The stream's `deadline_epoch_ms` passed, possibly before it started.
+    Deadline,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::MessageTooLong { limit, actual } => write!(
+                f,
+                "Message is too long: {} characters (limit is {})",
+                actual, limit
+            ),
+            CommandError::PythonError {
+                message, stderr, ..
+            } if stderr.is_empty() => f.write_str(message),
+            CommandError::PythonError {
+                message, stderr, ..
+            } => write!(f, "{}: {}", message, stderr.trim()),
+            CommandError::ModelLoadFailed {
+                model_id, message, ..
+            } => write!(f, "Failed to load model '{}': {}", model_id, message),
+            CommandError::Cancelled => f.write_str("Cancelled"),
+            CommandError::Timeout {
+                phase: TimeoutPhase::FirstChunk,
+            } => f.write_str("The handler didn't start responding in time"),
+            CommandError::Timeout {
+                phase: TimeoutPhase::Deadline,
+            } => f.write_str("The stream's deadline passed"),
+            CommandError::TokenizerUnavailable { model } => {
+                write!(f, "No tokenizer available for model '{}'", model)
+            }
+            CommandError::Failed { message } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        CommandError::Failed { message }
+    }
+}
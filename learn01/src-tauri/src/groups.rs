@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::error::CommandError;
+use crate::sink::ChunkSink;
+use crate::StreamChunk;
+
+/// Groups of related streams whose terminal chunks are released together,
+/// in the order the streams were submitted, once every one has ended.
+#[derive(Default)]
+pub struct StreamGroups {
+    groups: Mutex<HashMap<String, Group>>,
+    /// Source of `Group::id`.
+    next_id: AtomicU64,
+}
+
+#[derive(Default)]
+struct Group {
+    /// Tells this group apart from any later one opened under the same name.
+    id: u64,
+    members: Vec<Member>,
+    /// Set by `finish_stream_group`; no more streams may join.
+    finished: bool,
+}
+
+struct Member {
+    sink: ChunkSink,
+    /// Terminal chunk withheld until the group is released.
+    held: Option<StreamChunk>,
+    done: bool,
+}
+
+impl StreamGroups {
+    /// Ids of the open groups, sorted.
+    pub fn ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.groups.lock().unwrap().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Forget every group; chunks they were holding are dropped.
+    pub fn clear(&self) {
+        self.groups.lock().unwrap().clear();
+    }
+
+    fn start(&self, group_id: String) -> Result<(), String> {
+        if group_id.is_empty() {
+            return Err("Stream group id must not be empty".to_string());
+        }
+        let group = Group {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            ..Group::default()
+        };
+        let mut groups = self.groups.lock().unwrap();
+        if groups.contains_key(&group_id) {
+            return Err(format!("Stream group {:?} already exists", group_id));
+        }
+        groups.insert(group_id, group);
+        Ok(())
+    }
+
+    /// Add a stream to `group_id`. It holds its slot until the returned guard
+    /// is dropped, however the stream ends.
+    pub fn join(&self, group_id: &str, sink: &ChunkSink) -> Result<GroupMember<'_>, String> {
+        let mut groups = self.groups.lock().unwrap();
+        let group = groups
+            .get_mut(group_id)
+            .ok_or_else(|| format!("Unknown stream group: {:?}", group_id))?;
+        if group.finished {
+            return Err(format!("Stream group {:?} is already finished", group_id));
+        }
+
+        group.members.push(Member {
+            sink: sink.clone(),
+            held: None,
+            done: false,
+        });
+        Ok(GroupMember {
+            groups: self,
+            group_id: group_id.to_string(),
+            group: group.id,
+            slot: group.members.len() - 1,
+        })
+    }
+
+    /// Update a group, then emit its held chunks if that completed it.
+    fn update(&self, group_id: &str, f: impl FnOnce(&mut Group)) {
+        let released = {
+            let mut groups = self.groups.lock().unwrap();
+            let Some(group) = groups.get_mut(group_id) else {
+                return;
+            };
+            f(group);
+            if group.finished && group.members.iter().all(|member| member.done) {
+                groups.remove(group_id)
+            } else {
+                None
+            }
+        };
+
+        // Send outside the lock; a slow webview shouldn't block other groups
+        for member in released.into_iter().flat_map(|group| group.members) {
+            if let Some(chunk) = member.held {
+                if let Err(e) = member.sink.send(&chunk) {
+                    tracing::warn!(group_id, "failed to emit grouped chunk: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// A stream's place in its group.
+pub struct GroupMember<'a> {
+    groups: &'a StreamGroups,
+    group_id: String,
+    /// `Group::id` of the group joined.
+    group: u64,
+    slot: usize,
+}
+
+impl GroupMember<'_> {
+    /// Withhold the stream's terminal chunk until the group is released.
+    pub fn hold(&self, chunk: StreamChunk) {
+        self.update(|member| member.held = Some(chunk));
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Member)) {
+        self.groups.update(&self.group_id, |group| {
+            // The group may have been cleared and another opened in its name
+            if group.id == self.group {
+                f(&mut group.members[self.slot]);
+            }
+        });
+    }
+}
+
+impl Drop for GroupMember<'_> {
+    fn drop(&mut self) {
+        self.update(|member| member.done = true);
+    }
+}
+
+/// Open a group that streams can join through `StreamOptions::group_id`.
+#[tauri::command]
+pub fn start_stream_group(
+    groups: State<'_, StreamGroups>,
+    group_id: String,
+) -> Result<(), CommandError> {
+    Ok(groups.start(group_id)?)
+}
+
+/// Close a group to new streams. Its terminal chunks are emitted as soon as
+/// every member has ended, which may be right away. Returns the member count.
+#[tauri::command]
+pub fn finish_stream_group(
+    groups: State<'_, StreamGroups>,
+    group_id: String,
+) -> Result<usize, CommandError> {
+    let mut members = None;
+    groups.update(&group_id, |group| {
+        group.finished = true;
+        members = Some(group.members.len());
+    });
+    members.ok_or_else(|| format!("Unknown stream group: {:?}", group_id).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detached::DetachedStream;
+    use std::sync::Arc;
+
+    #[test]
+    fn members_of_a_cleared_group_leave_its_successor_alone() {
+        let groups = StreamGroups::default();
+        let sink = ChunkSink::Detached(Arc::new(DetachedStream::new(None)));
+        groups.start("batch".to_string()).unwrap();
+        let first = groups.join("batch", &sink).unwrap();
+        let second = groups.join("batch", &sink).unwrap();
+
+        groups.clear();
+        groups.start("batch".to_string()).unwrap();
+        let newer = groups.join("batch", &sink).unwrap();
+        second.hold(StreamChunk::new("complete"));
+        drop(second);
+        drop(first);
+
+        {
+            let groups = groups.groups.lock().unwrap();
+            let members = &groups["batch"].members;
+            assert_eq!(members.len(), 1);
+            assert!(members[0].held.is_none());
+            assert!(!members[0].done);
+        }
+        drop(newer);
+    }
+}
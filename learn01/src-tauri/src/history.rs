@@ -0,0 +1,295 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::AppHandle;
+use uuid::Uuid;
+
+use crate::error::CommandError;
+use crate::storage::{self, write_atomic};
+
+/// One persisted chat message; stored as a line in `<session_id>.jsonl`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HistoryMessage {
+    pub role: String,
+    pub content: String,
+    pub timestamp_ms: u64,
+    /// Seed the reply was requested with, on user messages, so the exchange
+    /// can be reproduced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Set on replies cut short by cancelling the stream.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+impl HistoryMessage {
+    pub fn new(role: &str, content: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.to_string(),
+            timestamp_ms: now_ms(),
+            seed: None,
+            truncated: false,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SessionStats {
+    session_id: String,
+    messages: usize,
+    bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct HistoryStats {
+    total_bytes: u64,
+    sessions: Vec<SessionStats>,
+}
+
+#[derive(Default, Serialize)]
+pub struct PruneReport {
+    freed_bytes: u64,
+    sessions_deleted: usize,
+    messages_trimmed: usize,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Directory holding one JSONL file per session.
+pub fn history_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    storage::app_data_subdir(app, "history")
+}
+
+/// Session ids become file names, so keep them to a safe alphabet.
+pub fn validate_session_id(session_id: &str) -> Result<(), String> {
+    let valid = !session_id.is_empty()
+        && session_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid session id: {:?}", session_id))
+    }
+}
+
+fn session_file(dir: &Path, session_id: &str) -> PathBuf {
+    dir.join(format!("{}.jsonl", session_id))
+}
+
+/// Append messages to a session's history file.
+pub fn append(dir: &Path, session_id: &str, messages: &[HistoryMessage]) -> Result<(), String> {
+    validate_session_id(session_id)?;
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create history directory: {}", e))?;
+
+    let mut lines = String::new();
+    for message in messages {
+        let line = serde_json::to_string(message).map_err(|e| e.to_string())?;
+        lines.push_str(&line);
+        lines.push('\n');
+    }
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(session_file(dir, session_id))
+        .and_then(|mut file| file.write_all(lines.as_bytes()))
+        .map_err(|e| format!("Failed to write history: {}", e))
+}
+
+/// Every message stored for a session, oldest first. A session with no
+/// history yet has no file, which is not an error.
+pub fn load(dir: &Path, session_id: &str) -> Result<Vec<HistoryMessage>, String> {
+    validate_session_id(session_id)?;
+    let contents = match fs::read_to_string(session_file(dir, session_id)) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read history: {}", e)),
+    };
+
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| format!("Corrupt history: {}", e)))
+        .collect()
+}
+
+/// The newest messages, oldest first, keeping at most `limit` of them and,
+/// when a budget is given, no more than `budget` tokens as measured by
+/// `count`.
+pub fn recent(
+    mut messages: Vec<HistoryMessage>,
+    limit: usize,
+    budget: Option<usize>,
+    count: impl Fn(&str) -> usize,
+) -> Vec<HistoryMessage> {
+    let mut used = 0;
+    let mut keep = 0;
+    for message in messages.iter().rev().take(limit) {
+        used += count(&message.content);
+        if budget.is_some_and(|budget| used > budget) {
+            break;
+        }
+        keep += 1;
+    }
+    messages.split_off(messages.len() - keep)
+}
+
+struct SessionFile {
+    session_id: String,
+    path: PathBuf,
+    bytes: u64,
+    modified: SystemTime,
+}
+
+fn session_files(dir: &Path) -> Result<Vec<SessionFile>, String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read history directory: {}", e)),
+    };
+
+    let mut files = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("jsonl") {
+            continue;
+        }
+        let Some(session_id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        files.push(SessionFile {
+            session_id: session_id.to_string(),
+            bytes: metadata.len(),
+            modified: metadata.modified().unwrap_or(UNIX_EPOCH),
+            path,
+        });
+    }
+    Ok(files)
+}
+
+#[tauri::command]
+pub fn history_stats(app: AppHandle) -> Result<HistoryStats, CommandError> {
+    let mut sessions = Vec::new();
+    let mut total_bytes = 0;
+
+    for file in session_files(&history_dir(&app)?)? {
+        let contents = fs::read_to_string(&file.path)
+            .map_err(|e| format!("Failed to read {:?}: {}", file.path, e))?;
+        total_bytes += file.bytes;
+        sessions.push(SessionStats {
+            session_id: file.session_id,
+            messages: contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .count(),
+            bytes: file.bytes,
+        });
+    }
+
+    sessions.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+    Ok(HistoryStats {
+        total_bytes,
+        sessions,
+    })
+}
+
+/// Copy a session's history into a new session, so the conversation can be
+/// continued down another path. Returns the new session's id.
+#[tauri::command]
+pub fn fork_session(app: AppHandle, source_id: String) -> Result<String, CommandError> {
+    validate_session_id(&source_id)?;
+    let dir = history_dir(&app)?;
+    let contents = match fs::read(session_file(&dir, &source_id)) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(format!("Session not found: {}", source_id).into())
+        }
+        Err(e) => return Err(format!("Failed to read history: {}", e).into()),
+    };
+
+    // Random ids keep concurrent forks of the same session apart
+    let session_id = Uuid::new_v4().simple().to_string();
+    write_atomic(&session_file(&dir, &session_id), &contents)
+        .map_err(|e| format!("Failed to write history: {}", e))?;
+    Ok(session_id)
+}
+
+/// Delete sessions untouched for `max_age_days`, then trim the oldest
+/// sessions (whole files first, then their oldest messages) until the
+/// history fits in `max_total_bytes`.
+#[tauri::command]
+pub fn prune_history(
+    app: AppHandle,
+    max_age_days: Option<u64>,
+    max_total_bytes: Option<u64>,
+) -> Result<PruneReport, CommandError> {
+    let mut report = PruneReport::default();
+    let mut files = session_files(&history_dir(&app)?)?;
+
+    if let Some(days) = max_age_days {
+        let max_age = Duration::from_secs(days.saturating_mul(24 * 60 * 60));
+        let cutoff = SystemTime::now().checked_sub(max_age).unwrap_or(UNIX_EPOCH);
+        let (expired, kept): (Vec<_>, Vec<_>) =
+            files.into_iter().partition(|file| file.modified < cutoff);
+        for file in expired {
+            fs::remove_file(&file.path).map_err(|e| format!("Failed to delete history: {}", e))?;
+            report.freed_bytes += file.bytes;
+            report.sessions_deleted += 1;
+        }
+        files = kept;
+    }
+
+    if let Some(budget) = max_total_bytes {
+        let total: u64 = files.iter().map(|file| file.bytes).sum();
+        let mut excess = total.saturating_sub(budget);
+        files.sort_by_key(|file| file.modified);
+
+        for file in files {
+            if excess == 0 {
+                break;
+            }
+            if file.bytes <= excess {
+                fs::remove_file(&file.path)
+                    .map_err(|e| format!("Failed to delete history: {}", e))?;
+                report.freed_bytes += file.bytes;
+                report.sessions_deleted += 1;
+                excess -= file.bytes;
+                continue;
+            }
+
+            // Dropping the oldest lines from this session is enough
+            let contents = fs::read_to_string(&file.path)
+                .map_err(|e| format!("Failed to read {:?}: {}", file.path, e))?;
+            let mut removed = 0u64;
+            let mut trimmed = 0;
+            let mut kept = String::new();
+            for line in contents.split_inclusive('\n') {
+                if removed < excess {
+                    removed += line.len() as u64;
+                    trimmed += 1;
+                } else {
+                    kept.push_str(line);
+                }
+            }
+            write_atomic(&file.path, kept.as_bytes())
+                .map_err(|e| format!("Failed to rewrite history: {}", e))?;
+            report.freed_bytes += file.bytes.saturating_sub(kept.len() as u64);
+            report.messages_trimmed += trimmed;
+            excess = 0;
+        }
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+/// Content it takes before the reply's language is detected, in bytes.
+/// Detection runs once, on this prefix.
+pub const SAMPLE_BYTES: usize = 300;
+
+/// The language a reply is written in, on the `language` chunk and the
+/// final `complete` chunk.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Language {
+    /// ISO 639-3 code, e.g. `"eng"` or `"ara"`.
+    pub code: String,
+    /// Between 0 and 1.
+    pub confidence: f64,
+}
+
+/// Detect the language of `text`, if it has any recognizable words.
+pub fn detect(text: &str) -> Option<Language> {
+    let info = whatlang::detect(text)?;
+    Some(Language {
+        code: info.lang().code().to_string(),
+        confidence: info.confidence(),
+    })
+}
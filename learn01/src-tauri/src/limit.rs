@@ -0,0 +1,102 @@
+use serde::Deserialize;
+use std::sync::Arc;
+use tiktoken_rs::CoreBPE;
+
+/// How text is counted against `max_tokens` and the history budget.
+#[derive(Clone, Default, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum TokenCounting {
+    /// Whitespace-separated words. Cheap, but only an approximation: most
+    /// tokenizers produce more tokens than words.
+    #[default]
+    Words,
+    /// Exact counts from `model`'s tokenizer, counted chunk by chunk.
+    Tokenizer { model: String },
+}
+
+/// Size of `text`: exact with an encoder, otherwise approximated by words.
+pub fn count(text: &str, encoder: Option<&CoreBPE>) -> usize {
+    match encoder {
+        Some(encoder) => encoder.encode_with_special_tokens(text).len(),
+        None => text.split_whitespace().count(),
+    }
+}
+
+/// Cuts the stream off once `max` tokens have been emitted.
+pub struct TokenLimit {
+    max: usize,
+    used: usize,
+    counter: Counter,
+}
+
+enum Counter {
+    Words {
+        /// Whether the last character seen was part of a word, so words
+        /// split across chunks are only counted once.
+        in_word: bool,
+    },
+    Tokenizer(Arc<CoreBPE>),
+}
+
+impl TokenLimit {
+    pub fn words(max: usize) -> Self {
+        Self {
+            max,
+            used: 0,
+            counter: Counter::Words { in_word: false },
+        }
+    }
+
+    pub fn tokenizer(max: usize, encoder: Arc<CoreBPE>) -> Self {
+        Self {
+            max,
+            used: 0,
+            counter: Counter::Tokenizer(encoder),
+        }
+    }
+
+    /// Feed the next piece of content. Returns the part that fits within the
+    /// limit and whether the limit has been reached.
+    pub fn push(&mut self, text: &str) -> (String, bool) {
+        match &mut self.counter {
+            Counter::Words { in_word } => {
+                for (index, c) in text.char_indices() {
+                    if c.is_whitespace() {
+                        *in_word = false;
+                    } else if !*in_word {
+                        // A word only counts once it starts, so the limit
+                        // trips at the start of the word past it
+                        *in_word = true;
+                        if self.used == self.max {
+                            return (text[..index].to_string(), true);
+                        }
+                        self.used += 1;
+                    }
+                }
+                (text.to_string(), false)
+            }
+            Counter::Tokenizer(encoder) => {
+                let tokens = encoder.encode_with_special_tokens(text);
+                let remaining = self.max - self.used;
+                if tokens.len() < remaining {
+                    self.used += tokens.len();
+                    return (text.to_string(), false);
+                }
+
+                self.used = self.max;
+                if tokens.len() == remaining {
+                    return (text.to_string(), true);
+                }
+                // A token can end mid-character; back off until it decodes
+                let mut keep = remaining;
+                while keep > 0 {
+                    if let Ok(kept) = encoder.decode(tokens[..keep].to_vec()) {
+                        return (kept, true);
+                    }
+                    keep -= 1;
+                }
+                (String::new(), true)
+            }
+        }
+    }
+}
@@ -1,19 +1,91 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::process::Command;
+mod ansi;
+mod backend;
+mod batch;
+mod cite;
+mod config;
+mod connectivity;
+mod detached;
+mod detect;
+mod diagnostics;
+mod error;
+mod groups;
+mod history;
+mod language;
+mod limit;
+mod memory;
+mod normalize;
+mod parse;
+mod pipeline;
+mod pools;
+mod power;
+mod process;
+mod profiles;
+mod progress;
+#[cfg(all(unix, feature = "pty"))]
+mod pty;
+mod python;
+mod rate;
+mod recording;
+mod repair;
+mod request;
+mod sandbox;
+mod scheduler;
+mod sink;
+mod sleep;
+mod state;
+mod stop;
+mod storage;
+mod streams;
+mod syntax;
+mod tokens;
+mod transcript;
+
+use ansi::{AnsiFilter, AnsiStripper};
+use backend::{Backend, ChatBackend, Connection, HttpBackend, PythonBackend};
+use config::{ConfigState, PythonConfig};
+use detached::DetachedStreams;
+use detect::DetectionCache;
+use error::{CommandError, TimeoutPhase};
+use groups::StreamGroups;
+use history::HistoryMessage;
+use limit::{TokenCounting, TokenLimit};
+use parse::{LineParser, ParseMode, Parsed};
+use pipeline::StreamPipeline;
+use pools::WorkerPools;
+use process::OutputLine;
+use progress::StreamProgress;
+use python::LastCommand;
+use recording::Recorder;
+use request::PythonRequest;
+use scheduler::{Queued, ReadScheduler, Turn};
 use serde::{Deserialize, Serialize};
+use sink::ChunkSink;
+use std::collections::VecDeque;
 use std::path::PathBuf;
-use std::process::Stdio;
-use tauri::Emitter;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command as AsyncCommand;
+use std::process::Command;
+use std::process::{ExitStatus, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use streams::StreamRegistry;
+use tauri::ipc::Channel;
+use tauri::{Emitter, Manager, State};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tokens::TokenCounter;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::sync::mpsc;
+use tracing::Instrument;
+use transcript::Transcript;
 
 #[derive(Serialize, Deserialize)]
 struct ChatResponse {
     success: bool,
     message: Option<String>,
     error: Option<String>,
+    /// Whatever a successful handler wrote to stderr, e.g. deprecation
+    /// warnings.
+    warnings: Option<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -23,11 +95,223 @@ struct StreamChunk {
     content: Option<String>,
     success: Option<bool>,
     error: Option<String>,
+    finish_reason: Option<String>,
+    exit_code: Option<i32>,
+    /// Full reply text, on the final `complete` chunk.
+    message: Option<String>,
+    /// Full reasoning text, on the final `complete` chunk.
+    reasoning: Option<String>,
+    /// Correlates the chunk with the request and the handler's own logs.
+    trace_id: Option<String>,
+    /// Token usage and cost, on `usage` chunks and the final `complete` chunk.
+    usage: Option<Usage>,
+    /// The call to make, on `tool_call` chunks.
+    tool_call: Option<ToolCall>,
+    /// Registry id of the stream, for `submit_tool_result` and cancelling.
+    stream_id: Option<String>,
+    /// Content generated before the stream was cancelled, on `cancelled`
+    /// chunks.
+    partial_message: Option<String>,
+    /// How far the task has got, on `progress` chunks.
+    progress: Option<progress::Progress>,
+    /// Resident memory of the handler, on `memory` chunks.
+    rss_bytes: Option<u64>,
+    /// The document cited as a reference number, on `citation` chunks.
+    citation: Option<cite::Citation>,
+    /// Every document cited in the reply, on the final `complete` chunk of
+    /// `extract_citations` streams.
+    citations: Option<Vec<cite::Citation>>,
+    /// Detected language of the reply, on `language` chunks and the final
+    /// `complete` chunk of `detect_language` streams.
+    language: Option<language::Language>,
+    /// Set by the handler when it couldn't honour the request's seed;
+    /// surfaced as a `warning` chunk instead.
+    #[serde(skip_serializing)]
+    seed_ignored: Option<bool>,
+}
+
+/// A tool the handler wants the frontend to run; answer it with
+/// `submit_tool_result`.
+#[derive(Clone, Serialize, Deserialize)]
+struct ToolCall {
+    id: String,
+    name: String,
+    arguments: serde_json::Value,
+}
+
+/// Usage reported by handlers that call metered APIs.
+#[derive(Clone, Serialize, Deserialize)]
+struct Usage {
+    prompt_tokens: Option<i64>,
+    completion_tokens: Option<i64>,
+    cost_usd: Option<f64>,
+}
+
+impl Usage {
+    fn is_valid(&self) -> bool {
+        let tokens_ok = [self.prompt_tokens, self.completion_tokens]
+            .iter()
+            .all(|tokens| tokens.is_none_or(|tokens| tokens >= 0));
+        let cost_ok = self
+            .cost_usd
+            .is_none_or(|cost| cost.is_finite() && cost >= 0.0);
+        tokens_ok && cost_ok
+    }
+}
+
+impl StreamChunk {
+    fn new(chunk_type: &str) -> Self {
+        Self {
+            chunk_type: chunk_type.to_string(),
+            content: None,
+            success: None,
+            error: None,
+            finish_reason: None,
+            exit_code: None,
+            message: None,
+            reasoning: None,
+            trace_id: None,
+            usage: None,
+            tool_call: None,
+            stream_id: None,
+            partial_message: None,
+            progress: None,
+            rss_bytes: None,
+            citation: None,
+            citations: None,
+            language: None,
+            seed_ignored: None,
+        }
+    }
+
+    fn is_content(&self) -> bool {
+        self.chunk_type == "chunk"
+    }
+
+    /// Whether this chunk ends the stream.
+    fn is_terminal(&self) -> bool {
+        matches!(self.chunk_type.as_str(), "complete" | "cancelled")
+    }
+}
+
+/// Stderr lines kept to explain a truncated stream.
+const STDERR_TAIL_LINES: usize = 20;
+
+/// History messages sent with `include_history` when no limit is given.
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+
+/// How often `debug` streams report the handler's memory.
+const MEMORY_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Log directive enabling DEBUG inside the spans of `debug` streams.
+const DEBUG_STREAMS: &str = "learn01[stream{debug=true}]=debug";
+
+/// Optional settings for `send_to_python_stream`.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct StreamOptions {
+    /// Coalesce content chunks that arrive within this window into one event.
+    batch_interval_ms: Option<u64>,
+    /// End the stream as soon as the content contains any of these.
+    stop_sequences: Vec<String>,
+    /// Persist the exchange to this session's history once it completes.
+    session_id: Option<String>,
+    /// Only emit chunks of these types; everything is emitted when unset.
+    chunk_filter: Option<Vec<String>>,
+    /// Record every raw handler line, with timestamps, to this `.ndjson`
+    /// file for later replay.
+    record_path: Option<PathBuf>,
+    /// Write every emitted chunk to this `.ndjson` file before emitting it,
+    /// flushed to disk every second.
+    transcript_path: Option<PathBuf>,
+    /// Flush the transcript to disk after every chunk, before the chunk is
+    /// emitted. Slower, but nothing the frontend saw can be lost.
+    durable: bool,
+    /// Correlation id passed to the handler; generated when absent.
+    trace_id: Option<String>,
+    /// Try to repair handler lines that aren't valid JSON instead of
+    /// dropping them, emitting a `parse_error` chunk for those beyond repair.
+    lenient_parse: bool,
+    /// Give the handler a pseudo-terminal as stdout so TTY-aware tools
+    /// render as in a shell; lines that aren't chunks are emitted as
+    /// `terminal` chunks. Needs the `pty` feature on Unix; pipes are used
+    /// otherwise.
+    use_pty: bool,
+    /// Keep the handler's stdin open so `submit_tool_result` can answer its
+    /// `tool_call` chunks.
+    interactive: bool,
+    /// Id to cancel the stream by with `cancel_python_stream`.
+    request_id: Option<String>,
+    /// End the stream with `finish_reason: "length"` once this many tokens
+    /// of content have been emitted, whatever the handler does.
+    max_tokens: Option<usize>,
+    /// How tokens are counted for `max_tokens`; words by default.
+    token_counting: TokenCounting,
+    /// Send the session's stored history along with the message.
+    include_history: bool,
+    /// Most history messages to include; 20 when unset.
+    history_limit: Option<usize>,
+    /// Drop the oldest included messages beyond this many tokens, counted
+    /// with `token_counting`.
+    history_token_budget: Option<usize>,
+    /// Stream group, opened with `start_stream_group`, whose terminal chunks
+    /// are released together in submission order.
+    group_id: Option<String>,
+    /// Drop a content chunk identical to the one just before it, for
+    /// handlers that sometimes send chunks twice. Legitimately repeated text
+    /// split differently is unaffected.
+    dedup_consecutive: bool,
+    /// Keep the stream running when the page that started it goes away, in
+    /// its own process group on Unix. The command returns at once; chunks
+    /// are buffered for `reattach_stream`. Needs a `session_id`; only
+    /// `send_to_python_stream` supports it.
+    detached: bool,
+    /// Text emitted as the first content chunk, e.g. a code fence; part of
+    /// the reply like anything the handler sends.
+    content_prefix: Option<String>,
+    /// Text emitted as the last content chunk, just before `complete`.
+    content_suffix: Option<String>,
+    /// When cancelled, still save the exchange to the session history, with
+    /// the reply marked truncated.
+    save_partial: bool,
+    /// Sampling seed passed to the handler, for reproducible output; stored
+    /// with the message in the session history.
+    seed: Option<u64>,
+    /// Log this stream's raw lines, emits, pipeline state and handler
+    /// lifecycle at DEBUG, whatever the global log level, and emit a
+    /// `memory` chunk with the handler's resident memory every second.
+    debug: bool,
+    /// Absolute deadline in Unix epoch milliseconds, e.g. from the
+    /// frontend's abort signal. The handler is killed when it passes and the
+    /// stream fails with a `deadline` timeout; a deadline already past fails
+    /// before anything is started.
+    deadline_epoch_ms: Option<u64>,
+    /// Kill the handler and fail with a `first_chunk` timeout when it hasn't
+    /// sent a single chunk this long after starting.
+    first_chunk_timeout_ms: Option<u64>,
+    /// Replace `[[cite:doc_id]]` markers in the content with reference
+    /// numbers like `[1]`, emitting a `citation` chunk the first time each
+    /// document is cited.
+    extract_citations: bool,
+    /// Detect the reply's language from its first few hundred characters,
+    /// emitted once as a `language` chunk and included on `complete`.
+    detect_language: bool,
+}
+
+impl StreamOptions {
+    fn wants(&self, chunk: &StreamChunk) -> bool {
+        self.chunk_filter
+            .as_ref()
+            .is_none_or(|types| types.contains(&chunk.chunk_type))
+    }
 }
 
 #[tauri::command]
-fn check_python_available() -> Result<bool, String> {
-    let python_cmd = if cfg!(target_os = "windows") { "python" } else { "python3" };
+fn check_python_available(
+    app: tauri::AppHandle,
+    config: State<'_, ConfigState>,
+) -> Result<bool, CommandError> {
+    let python_cmd = python::interpreter(&app, &config.get());
 
     match Command::new(python_cmd).arg("--version").output() {
         Ok(output) => Ok(output.status.success()),
@@ -36,111 +320,950 @@ fn check_python_available() -> Result<bool, String> {
 }
 
 #[tauri::command]
-fn send_to_python(message: String) -> Result<ChatResponse, String> {
-    // Python exec
-    let python_cmd = if cfg!(target_os = "windows") { "python" } else { "python3" };
-
-    // Get the path to the Python script
-    let python_script = if cfg!(debug_assertions) {
-        // Development: get absolute path
-        let mut path = std::env::current_dir()
-            .map_err(|e| format!("Failed to get current directory: {}", e))?;
-        path.push("python");
-        path.push("chat_handler.py");
-        path
-    } else {
-        // Production: bundle with the app
-        PathBuf::from("python/chat_handler.py")
-    };
+async fn send_to_python(
+    app: tauri::AppHandle,
+    registry: State<'_, StreamRegistry>,
+    config: State<'_, ConfigState>,
+    message: String,
+    trace_id: Option<String>,
+    request_id: Option<String>,
+) -> Result<ChatResponse, CommandError> {
+    let config = config.get();
+    config.check_message(&message)?;
+    let trace_id = request::trace_id(trace_id.as_deref());
+    let span = tracing::info_span!("send_to_python", %trace_id);
+    run_handler(
+        &app,
+        &registry,
+        config,
+        &message,
+        &trace_id,
+        request_id.as_deref(),
+    )
+    .instrument(span)
+    .await
+}
 
-    if !python_script.exists() {
-        return Err(format!("Python script not found at: {:?}", python_script));
+/// Run the handler to completion and parse its single response. The call
+/// is registered under `request_id` so `cancel_python_stream` can abort it.
+async fn run_handler(
+    app: &tauri::AppHandle,
+    registry: &StreamRegistry,
+    config: PythonConfig,
+    message: &str,
+    trace_id: &str,
+    request_id: Option<&str>,
+) -> Result<ChatResponse, CommandError> {
+    let mut python_cmd = python::handler_command(app, &config, trace_id)?;
+    let request = PythonRequest::chat(&config.prompt(message), trace_id).to_line()?;
+    let operation = registry.register(request_id, None)?;
+
+    if config.inherit_stdio {
+        let mut child = python::spawn(
+            app,
+            &config,
+            python_cmd.stdout(Stdio::inherit()).stderr(Stdio::inherit()),
+        )
+        .map_err(|e| format!("Failed to execute python: {}", e))?;
+        process::write_request(&mut child, &request).await?;
+        let status = tokio::select! {
+            status = process::reap(&mut child) => status?,
+            _ = operation.cancelled() => {
+                process::terminate(&mut child).await?;
+                return Err(CommandError::Cancelled);
+            }
+        };
+        let success = status.is_some_and(|status| status.success());
+        return Ok(ChatResponse {
+            success,
+            message: None,
+            warnings: None,
+            error: (!success).then(|| match status {
+                Some(status) => format!("Python script failed: {}", status),
+                None => "Python script failed".to_string(),
+            }),
+        });
     }
 
     // Execute python script
-    let output = Command::new(python_cmd)
-        .arg(python_script)
-        .arg(&message)
-        .output()
-        .map_err(|e| format!("Failed to execute python: {}", e))?;
+    let mut child = python::spawn(
+        app,
+        &config,
+        python_cmd.stdout(Stdio::piped()).stderr(Stdio::piped()),
+    )
+    .map_err(|e| format!("Failed to execute python: {}", e))?;
+    tracing::info!(pid = child.id(), "spawned handler");
+    process::write_request(&mut child, &request).await?;
+
+    // Dropping the wait on cancel drops the child, which kills it
+    let output = tokio::select! {
+        output = child.wait_with_output() => {
+            output.map_err(|e| format!("Failed to wait for python: {}", e))?
+        }
+        _ = operation.cancelled() => {
+            tracing::info!("request cancelled");
+            return Err(CommandError::Cancelled);
+        }
+    };
 
     if output.status.success() {
         let stdout = String::from_utf8_lossy(&output.stdout);
-        let response: ChatResponse = serde_json::from_str(&stdout)
+        let mut response: ChatResponse = serde_json::from_str(&stdout)
             .map_err(|e| format!("Failed to parse python response: {}", e))?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stderr.trim().is_empty() {
+            response.warnings = Some(stderr.trim().to_string());
+        }
+        if config.normalize_output {
+            normalize::response(&mut response);
+        }
         Ok(response)
     } else {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        Err(format!("Python script failed: {}", stderr))
+        tracing::warn!(status = %output.status, "handler failed");
+        Err(format!("Python script failed: {}", stderr).into())
     }
 }
 
 #[tauri::command]
 async fn send_to_python_stream(
     window: tauri::Window,
+    registry: State<'_, StreamRegistry>,
+    config: State<'_, ConfigState>,
     message: String,
-) -> Result<(), String> {
-    // Python exec
-    let python_cmd = if cfg!(target_os = "windows") { "python" } else { "python3" };
-
-    // Get the path to the Python script
-    let python_script = if cfg!(debug_assertions) {
-        // Development: get absolute path
-        let mut path = std::env::current_dir()
-            .map_err(|e| format!("Failed to get current directory: {}", e))?;
-        path.push("python");
-        path.push("chat_handler.py");
-        path
-    } else {
-        // Production: bundle with the app
-        PathBuf::from("python/chat_handler.py")
+    options: Option<StreamOptions>,
+) -> Result<(), CommandError> {
+    let app = window.app_handle().clone();
+    let sink = ChunkSink::Window(Box::new(window));
+    let options = options.unwrap_or_default();
+    if options.detached {
+        return detached::spawn(app, sink, config.get(), message, options);
+    }
+    run_stream(&app, &sink, &registry, config.get(), &message, &options).await
+}
+
+/// Like `send_to_python_stream`, but chunks go straight to the caller through
+/// `on_chunk` instead of a global event.
+#[tauri::command]
+async fn send_to_python_stream_channel(
+    app: tauri::AppHandle,
+    registry: State<'_, StreamRegistry>,
+    config: State<'_, ConfigState>,
+    message: String,
+    options: Option<StreamOptions>,
+    on_chunk: Channel<StreamChunk>,
+) -> Result<(), CommandError> {
+    let sink = ChunkSink::Channel(on_chunk);
+    run_stream(
+        &app,
+        &sink,
+        &registry,
+        config.get(),
+        &message,
+        &options.unwrap_or_default(),
+    )
+    .await
+}
+
+/// Stream the clipboard's text through the handler, as if it had been typed
+/// as the message.
+#[tauri::command]
+async fn send_clipboard_to_python_stream(
+    window: tauri::Window,
+    registry: State<'_, StreamRegistry>,
+    config: State<'_, ConfigState>,
+    options: Option<StreamOptions>,
+) -> Result<(), CommandError> {
+    let message = window
+        .app_handle()
+        .clipboard()
+        .read_text()
+        .map_err(|_| "The clipboard doesn't contain any text".to_string())?;
+    if message.trim().is_empty() {
+        return Err("The clipboard is empty".to_string().into());
+    }
+
+    let app = window.app_handle().clone();
+    let sink = ChunkSink::Window(Box::new(window));
+    run_stream(
+        &app,
+        &sink,
+        &registry,
+        config.get(),
+        &message,
+        &options.unwrap_or_default(),
+    )
+    .await
+}
+
+/// Run the handler and feed its output through the pipeline into `sink`.
+async fn run_stream(
+    app: &tauri::AppHandle,
+    sink: &ChunkSink,
+    registry: &StreamRegistry,
+    config: PythonConfig,
+    message: &str,
+    options: &StreamOptions,
+) -> Result<(), CommandError> {
+    let trace_id = request::trace_id(options.trace_id.as_deref());
+    let span = tracing::info_span!(
+        "stream",
+        %trace_id,
+        debug = options.debug,
+        stream_id = tracing::field::Empty
+    );
+    run_handler_stream(app, sink, registry, config, message, options, &trace_id)
+        .instrument(span)
+        .await
+}
+
+async fn run_handler_stream(
+    app: &tauri::AppHandle,
+    sink: &ChunkSink,
+    registry: &StreamRegistry,
+    config: PythonConfig,
+    message: &str,
+    options: &StreamOptions,
+    trace_id: &str,
+) -> Result<(), CommandError> {
+    check_stream(&config, message, options)?;
+
+    let counts_tokens = options.max_tokens.is_some()
+        || (options.include_history && options.history_token_budget.is_some());
+    let encoder = match &options.token_counting {
+        TokenCounting::Tokenizer { model } if counts_tokens => {
+            Some(app.state::<TokenCounter>().encoder_for_model(model).await?)
+        }
+        _ => None,
     };
+    let limit = options.max_tokens.map(|max| match &encoder {
+        Some(encoder) => TokenLimit::tokenizer(max, encoder.clone()),
+        None => TokenLimit::words(max),
+    });
 
-    if !python_script.exists() {
-        return Err(format!("Python script not found at: {:?}", python_script));
+    let mut request = PythonRequest::chat(&config.prompt(message), trace_id);
+    request.seed = options.seed;
+    if options.include_history {
+        let session_id = options
+            .session_id
+            .as_deref()
+            .ok_or_else(|| "include_history needs a session_id".to_string())?;
+        request.history = history::recent(
+            history::load(&history::history_dir(app)?, session_id)?,
+            options.history_limit.unwrap_or(DEFAULT_HISTORY_LIMIT),
+            options.history_token_budget,
+            |text| limit::count(text, encoder.as_deref()),
+        );
     }
 
-    // Execute python script
-    let mut child = AsyncCommand::new(python_cmd)
-        .arg(python_script)
-        .arg(&message)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|e| format!("Failed to execute python: {}", e))?;
+    let group = options
+        .group_id
+        .as_deref()
+        .map(|group_id| app.state::<StreamGroups>().inner().join(group_id, sink))
+        .transpose()?;
 
-    // Get stdout handle
-    let stdout = child
-        .stdout
-        .take()
-        .ok_or_else(|| "Failed to capture stdout".to_string())?;
+    let mut recorder = options
+        .record_path
+        .as_deref()
+        .map(Recorder::create)
+        .transpose()?;
 
-    //Create buffered reader for line-by-line reading
-    let reader = BufReader::new(stdout);
-    let mut lines = reader.lines();
+    // Converted at the last moment, so time spent getting here counts
+    let deadline = options
+        .deadline_epoch_ms
+        .map(deadline_instant)
+        .transpose()?;
+    let Connection {
+        output: mut lines,
+        mut child,
+        mut stdin,
+    } = match &config.backend {
+        Backend::Python => {
+            let backend = PythonBackend {
+                app,
+                config: &config,
+                options,
+                trace_id,
+            };
+            backend.open(&request).await?
+        }
+        Backend::Http { url } => HttpBackend { url }.open(&request).await?,
+    };
+
+    let mut first_chunk_deadline = options
+        .first_chunk_timeout_ms
+        .map(|ms| tokio::time::Instant::now() + Duration::from_millis(ms));
+
+    // Anything on stderr is surfaced as a warning; the handler reports real
+    // failures in-band
+    let mut stderr_lines = child
+        .as_mut()
+        .and_then(|child| child.stderr.take())
+        .map(|stderr| BufReader::new(stderr).lines());
+
+    let stream = registry.register(options.request_id.as_deref(), options.session_id.as_deref())?;
+    tracing::Span::current().record("stream_id", stream.id());
+    let pid = child.as_ref().and_then(|child| child.id());
+    if let Some(pid) = pid {
+        stream.set_pid(pid);
+    }
+    let mut memory_samples = pid
+        .filter(|_| options.debug)
+        .map(|pid| (pid, tokio::time::interval(MEMORY_SAMPLE_INTERVAL)));
+    let mut input = options.interactive.then(|| stream.accept_input());
+    let mut transcript = options
+        .transcript_path
+        .as_deref()
+        .map(|path| Transcript::create(path, options.durable))
+        .transpose()?;
+    let mut emit = async |mut chunk: StreamChunk| {
+        if options.wants(&chunk) {
+            tracing::debug!(chunk_type = %chunk.chunk_type, content = ?chunk.content, "emit");
+            chunk.trace_id = Some(trace_id.to_string());
+            chunk.stream_id = Some(stream.id().to_string());
+            if let Some(transcript) = &mut transcript {
+                transcript.write(&chunk).await?;
+            }
+            match &group {
+                Some(group) if chunk.is_terminal() => group.hold(chunk),
+                _ => sink.send(&chunk)?,
+            }
+        }
+        Ok::<_, String>(())
+    };
+    let mut pipeline = StreamPipeline::new(options, limit, config.max_emits_per_sec);
+    let mut parser = LineParser::new(config.parse_mode, config.detect_framing);
+    let mut stderr_tail = VecDeque::new();
+    let mut truncated = None;
+    let scheduler = app.state::<ReadScheduler>();
+    let mut turn: Option<Turn> = None;
+    let mut queued: Option<Queued> = None;
+    let (mut ansi, mut stderr_ansi) = match config.strip_ansi {
+        true => (Some(AnsiFilter::default()), Some(AnsiStripper::default())),
+        false => (None, None),
+    };
 
     // Read and emit each line as it comes
-    while let Some(line) = lines.next_line().await.map_err(|e| e.to_string())? {
-        if let Ok(chunk) = serde_json::from_str::<StreamChunk>(&line) {
-            window
-                .emit("stream-chunk", &chunk)
-                .map_err(|e| e.to_string())?;
+    loop {
+        let flush_at = pipeline.deadline();
+        let turn_ends = turn.as_ref().map(Turn::ends);
+        // Waiting for a turn is a branch of its own, so cancels, deadlines
+        // and input still get through while the stream is in line
+        let crowded = config
+            .fair_read_above
+            .is_some_and(|above| registry.len() > above);
+        if !crowded {
+            queued = None;
+        } else if turn.is_none() && queued.is_none() {
+            queued = Some(scheduler.queue());
+        }
+        tokio::select! {
+            next = next_turn(&mut queued), if queued.is_some() => {
+                queued = None;
+                turn = Some(next);
+            }
+            line = lines.next_line(), if queued.is_none() => {
+                let (ready, ended) = match line.map_err(|e| e.to_string())? {
+                    Some(OutputLine { text: line, terminated }) => {
+                        tracing::debug!(line, terminated, "read line");
+                        if turn.as_mut().is_some_and(Turn::take_line) {
+                            turn = None;
+                        }
+                        if let Some(recorder) = &mut recorder {
+                            recorder.record(&line)?;
+                        }
+                        (parser.push(line, terminated), false)
+                    }
+                    None => {
+                        tracing::debug!("handler output ended");
+                        (parser.finish(), true)
+                    }
+                };
+                if parser.take_switch() {
+                    let mode = parser.mode().as_str();
+                    tracing::warn!(mode, "handler output isn't NDJSON, switching framing");
+                    let mut chunk = StreamChunk::new("framing_switched");
+                    chunk.content = Some(mode.to_string());
+                    for chunk in pipeline.push(chunk) {
+                        emit(chunk).await?;
+                    }
+                }
+                for (line, terminated, parsed) in ready {
+                    let parsed = match parsed {
+                        Parsed::Chunk(chunk) => Some(*chunk),
+                        Parsed::Skip => None,
+                        // The handler died mid-chunk; this is the last of its output
+                        Parsed::Invalid(..) if !terminated => {
+                            truncated = Some(line);
+                            None
+                        }
+                        Parsed::Invalid(json, e) if options.lenient_parse => {
+                            Some(repair::parse_lenient(&json, e))
+                        }
+                        // Under a terminal, tools print progress and prompts too
+                        Parsed::Invalid(..) if options.use_pty => {
+                            let mut chunk = StreamChunk::new("terminal");
+                            chunk.content = Some(line);
+                            Some(chunk)
+                        }
+                        Parsed::Invalid(..) => None,
+                    };
+                    if let Some(mut chunk) = parsed {
+                        first_chunk_deadline = None;
+                        if let Some(ansi) = &mut ansi {
+                            ansi.chunk(&mut chunk);
+                        }
+                        if config.normalize_output {
+                            normalize::chunk(&mut chunk);
+                        }
+                        if let (Some(session_id), Some(progress)) = (&options.session_id, &chunk.progress) {
+                            app.state::<StreamProgress>().update(session_id, progress);
+                        }
+                        if chunk.seed_ignored.take() == Some(true) {
+                            let mut warning = StreamChunk::new("warning");
+                            warning.content = Some(format!(
+                                "The handler ignored seed {}; the output is not reproducible",
+                                options.seed.unwrap_or_default()
+                            ));
+                            for chunk in pipeline.push(warning) {
+                                emit(chunk).await?;
+                            }
+                        }
+                        for chunk in pipeline.push(chunk) {
+                            emit(chunk).await?;
+                        }
+                        let (held_by_stop, batched) = pipeline.held();
+                        tracing::debug!(held_by_stop, batched, "pipeline state");
+                        if pipeline.is_finished() {
+                            let status = terminate(&mut child).await?;
+                            tracing::debug!(?status, "handler killed after the pipeline finished");
+                            save_exchange(app, options, message, pipeline.content(), false)?;
+                            return Ok(());
+                        }
+                    }
+                }
+                if ended {
+                    break;
+                }
+            }
+            line = next_line(&mut stderr_lines), if stderr_lines.is_some() => {
+                match line {
+                    Ok(Some(mut line)) => {
+                        if let Some(stripper) = &mut stderr_ansi {
+                            line = stripper.push(&line);
+                        }
+                        remember_stderr(&mut stderr_tail, &line);
+                        let mut chunk = StreamChunk::new("warning");
+                        chunk.content = Some(line);
+                        for chunk in pipeline.push(chunk) {
+                            emit(chunk).await?;
+                        }
+                    }
+                    _ => {
+                        tracing::debug!("handler closed stderr");
+                        stderr_lines = None;
+                    }
+                }
+            }
+            Some(line) = next_input(&mut input), if input.is_some() => {
+                tracing::debug!(line = line.trim_end(), "passing input to handler");
+                if let Some(stdin) = &mut stdin {
+                    if let Err(e) = stdin.write_all(line.as_bytes()).await {
+                        tracing::warn!("failed to pass input to handler: {}", e);
+                    }
+                }
+            }
+            Some(pid) = next_sample(&mut memory_samples), if memory_samples.is_some() => {
+                match memory::rss_bytes(pid).await {
+                    Ok(rss_bytes) => {
+                        let mut chunk = StreamChunk::new("memory");
+                        chunk.rss_bytes = Some(rss_bytes);
+                        for chunk in pipeline.push(chunk) {
+                            emit(chunk).await?;
+                        }
+                    }
+                    Err(e) => {
+                        tracing::debug!("stopped sampling memory: {}", e);
+                        memory_samples = None;
+                    }
+                }
+            }
+            _ = batch::sleep_until(flush_at) => {
+                for chunk in pipeline.flush() {
+                    emit(chunk).await?;
+                }
+            }
+            _ = batch::sleep_until(turn_ends) => {
+                turn = None;
+            }
+            _ = batch::sleep_until(first_chunk_deadline) => {
+                return time_out_first_chunk(&mut child).await;
+            }
+            _ = batch::sleep_until(deadline) => {
+                for chunk in pipeline.finish() {
+                    emit(chunk).await?;
+                }
+                let status = terminate(&mut child).await?;
+                tracing::warn!(?status, "handler killed at the stream's deadline");
+                return Err(CommandError::Timeout { phase: TimeoutPhase::Deadline });
+            }
+            _ = stream.suspended() => {
+                // The handler may not survive the sleep, so what was
+                // generated so far goes out while there is still time
+                for chunk in pipeline.checkpoint() {
+                    emit(chunk).await?;
+                }
+                emit(StreamChunk::new("suspended")).await?;
+                if config.cancel_streams_after_sleep {
+                    stream.cancel();
+                }
+            }
+            _ = stream.resumed() => {
+                // A handler killed while the machine slept may leave its
+                // output open, and the stream would hang
+                let exited = match &mut child {
+                    Some(child) => child.try_wait().map_err(|e| e.to_string())?,
+                    None => None,
+                };
+                if let Some(status) = exited.filter(|status| !status.success()) {
+                    tracing::warn!(?status, "handler died while the system was asleep");
+                    for chunk in pipeline.finish() {
+                        emit(chunk).await?;
+                    }
+                    let mut chunk = StreamChunk::new("error");
+                    chunk.success = Some(false);
+                    chunk.error = Some("The handler died while the system was asleep".to_string());
+                    chunk.exit_code = status.code();
+                    emit(chunk).await?;
+                    return Ok(());
+                }
+                if config.cancel_streams_after_sleep {
+                    stream.cancel();
+                }
+            }
+            _ = stream.cancelled() => {
+                tracing::info!("stream cancelled");
+                // What was generated so far goes out before the handler is
+                // killed
+                for chunk in pipeline.finish() {
+                    emit(chunk).await?;
+                }
+                let status = terminate(&mut child).await?;
+                tracing::debug!(?status, "handler killed on cancel");
+                let mut chunk = StreamChunk::new("cancelled");
+                chunk.success = Some(false);
+                chunk.exit_code = status.and_then(|status| status.code());
+                chunk.partial_message = Some(pipeline.content().to_string());
+                emit(chunk).await?;
+                if options.save_partial {
+                    save_exchange(app, options, message, pipeline.content(), true)?;
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    // Plain text has no end marker of its own
+    if parser.mode() == ParseMode::PlainText && truncated.is_none() {
+        let mut done = StreamChunk::new("complete");
+        done.success = Some(true);
+        for chunk in pipeline.push(done) {
+            emit(chunk).await?;
         }
     }
+    for chunk in pipeline.finish() {
+        emit(chunk).await?;
+    }
+
+    // The handler reports its own failures in-band, so only reap here
+    let status = match &mut child {
+        Some(child) => process::reap(child).await?,
+        None => None,
+    };
+    tracing::info!(status = ?status, "stream finished");
 
-    child.wait().await.map_err(|e| e.to_string())?;
+    // A half-written chunk is reported along with what the handler said on
+    // its way down
+    if let Some(partial) = truncated {
+        while let Ok(Some(line)) = next_line(&mut stderr_lines).await {
+            remember_stderr(&mut stderr_tail, &line);
+        }
+        emit(truncated_chunk(partial, status, stderr_tail)).await?;
+    }
+    save_exchange(app, options, message, pipeline.content(), false)?;
 
     Ok(())
 }
 
+/// Reject a stream request that can't succeed, before anything is started
+/// for it.
+fn check_stream(
+    config: &PythonConfig,
+    message: &str,
+    options: &StreamOptions,
+) -> Result<(), CommandError> {
+    config.check_message(message)?;
+    if config.inherit_stdio {
+        return Err(
+            "Streaming needs the handler's output; disable inherit_stdio to stream"
+                .to_string()
+                .into(),
+        );
+    }
+    if let Some(session_id) = &options.session_id {
+        history::validate_session_id(session_id)?;
+    }
+    if options.seed.is_some_and(|seed| seed > i64::MAX as u64) {
+        return Err(format!("seed must be at most {}", i64::MAX).into());
+    }
+    if let Some(epoch_ms) = options.deadline_epoch_ms {
+        deadline_instant(epoch_ms)?;
+    }
+    Ok(())
+}
+
+/// The monotonic instant matching a wall-clock deadline, as long as it's
+/// still ahead.
+fn deadline_instant(epoch_ms: u64) -> Result<tokio::time::Instant, CommandError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?;
+    match Duration::from_millis(epoch_ms).checked_sub(now) {
+        Some(remaining) if !remaining.is_zero() => Ok(tokio::time::Instant::now() + remaining),
+        _ => Err(CommandError::Timeout {
+            phase: TimeoutPhase::Deadline,
+        }),
+    }
+}
+
+/// Report of a handler that died partway through writing a chunk.
+fn truncated_chunk(
+    partial: String,
+    status: Option<ExitStatus>,
+    stderr_tail: VecDeque<String>,
+) -> StreamChunk {
+    let mut chunk = StreamChunk::new("truncated");
+    chunk.success = Some(false);
+    chunk.content = Some(partial);
+    chunk.exit_code = status.and_then(|status| status.code());
+    if !stderr_tail.is_empty() {
+        chunk.error = Some(Vec::from(stderr_tail).join("\n"));
+    }
+    chunk
+}
+
+/// Keep the last `STDERR_TAIL_LINES` lines of stderr.
+fn remember_stderr(tail: &mut VecDeque<String>, line: &str) {
+    if tail.len() == STDERR_TAIL_LINES {
+        tail.pop_front();
+    }
+    tail.push_back(line.to_string());
+}
+
+/// Next line from an optional reader; only polled while it is `Some`.
+async fn next_line<R: AsyncBufRead + Unpin>(
+    lines: &mut Option<Lines<R>>,
+) -> std::io::Result<Option<String>> {
+    match lines {
+        Some(lines) => lines.next_line().await,
+        None => Ok(None),
+    }
+}
+
+/// Wait for the next memory sample of a `debug` stream's handler.
+async fn next_sample(samples: &mut Option<(u32, tokio::time::Interval)>) -> Option<u32> {
+    match samples {
+        Some((pid, interval)) => {
+            interval.tick().await;
+            Some(*pid)
+        }
+        None => None,
+    }
+}
+
+/// Kill the handler behind a stream, if there is one.
+async fn terminate(
+    child: &mut Option<tokio::process::Child>,
+) -> Result<Option<ExitStatus>, String> {
+    match child {
+        Some(child) => process::terminate(child).await,
+        None => Ok(None),
+    }
+}
+
+/// Kill a handler that hasn't sent its first chunk in time.
+async fn time_out_first_chunk(
+    child: &mut Option<tokio::process::Child>,
+) -> Result<(), CommandError> {
+    let status = terminate(child).await?;
+    tracing::warn!(?status, "handler killed before sending its first chunk");
+    Err(CommandError::Timeout {
+        phase: TimeoutPhase::FirstChunk,
+    })
+}
+
+/// The turn `queued` is waiting for; only polled while in line.
+async fn next_turn(queued: &mut Option<Queued>) -> Turn {
+    match queued {
+        Some(queued) => queued.await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Next queued stdin line; only polled while there is a receiver.
+async fn next_input(input: &mut Option<mpsc::UnboundedReceiver<String>>) -> Option<String> {
+    match input {
+        Some(input) => input.recv().await,
+        None => None,
+    }
+}
+
+/// Record a finished exchange in the session history, if the stream has one.
+fn save_exchange(
+    app: &tauri::AppHandle,
+    options: &StreamOptions,
+    message: &str,
+    reply: &str,
+    truncated: bool,
+) -> Result<(), String> {
+    let Some(session_id) = &options.session_id else {
+        return Ok(());
+    };
+
+    let mut sent = HistoryMessage::new("user", message);
+    sent.seed = options.seed;
+    let mut messages = vec![sent];
+    if !reply.is_empty() {
+        let mut answer = HistoryMessage::new("assistant", reply);
+        answer.truncated = truncated;
+        messages.push(answer);
+    }
+    history::append(&history::history_dir(app)?, session_id, &messages)
+}
+
+/// Cancel every running stream, stop the worker pools, drop cached Python
+/// state, then re-run the self-test so the UI can offer a "restart Python"
+/// action without restarting the app.
+#[tauri::command]
+async fn restart_python_subsystem(
+    app: tauri::AppHandle,
+    registry: State<'_, StreamRegistry>,
+    pools: State<'_, WorkerPools>,
+    config: State<'_, ConfigState>,
+) -> Result<diagnostics::Diagnostics, CommandError> {
+    registry.cancel_all();
+    if !registry.wait_idle(Duration::from_secs(5)).await {
+        return Err("Timed out waiting for running streams to stop"
+            .to_string()
+            .into());
+    }
+    pools.shutdown_all().await;
+
+    app.state::<DetectionCache>().clear();
+    let diagnostics = diagnostics::self_test(&app, &config.get()).await;
+    app.emit("subsystem-restarted", &diagnostics)
+        .map_err(|e| e.to_string())?;
+
+    Ok(diagnostics)
+}
+
+/// Launch the handler with the interpreter from a conda environment.
+#[tauri::command]
+async fn use_conda_env(
+    config: State<'_, ConfigState>,
+    name: String,
+) -> Result<PathBuf, CommandError> {
+    let interpreter = python::conda_interpreter(&name).await?;
+    config.update(|config| config.interpreter = Some(interpreter.clone()));
+    Ok(interpreter)
+}
+
 fn main() {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"))
+                // Streams started with `debug` log everything
+                .add_directive(DEBUG_STREAMS.parse().unwrap()),
+        )
+        .init();
+
     // learn01_lib::run();
     tauri::Builder::default()
+        .plugin(tauri_plugin_clipboard_manager::init())
+        .manage(StreamRegistry::default())
+        .manage(ConfigState::default())
+        .manage(TokenCounter::default())
+        .manage(StreamGroups::default())
+        .manage(WorkerPools::default())
+        .manage(StreamProgress::default())
+        .manage(DetachedStreams::default())
+        .manage(ReadScheduler::default())
+        .manage(DetectionCache::default())
+        .manage(LastCommand::default())
+        .setup(|app| {
+            // Startup self-test; problems are reported but never fatal
+            let config = app.state::<ConfigState>().get();
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let diagnostics = diagnostics::self_test(&handle, &config).await;
+                if !diagnostics.is_healthy() {
+                    tracing::warn!(
+                        diagnostics = %serde_json::to_string(&diagnostics).unwrap_or_default(),
+                        "Python self-test failed"
+                    );
+                }
+            });
+            tauri::async_runtime::spawn(pools::stop_idle_workers(app.handle().clone()));
+            tauri::async_runtime::spawn(sleep::watch_for_sleep(app.handle().clone()));
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             send_to_python,
             check_python_available,
-            send_to_python_stream
+            send_to_python_stream,
+            send_to_python_stream_channel,
+            send_clipboard_to_python_stream,
+            restart_python_subsystem,
+            use_conda_env,
+            groups::start_stream_group,
+            groups::finish_stream_group,
+            connectivity::test_upstream_connectivity,
+            pools::ensure_pool,
+            pools::send_to_pool,
+            pools::shutdown_pool,
+            pools::load_model,
+            pools::unload_model,
+            pools::loaded_models,
+            backend::set_backend,
+            config::get_python_config,
+            config::set_python_config,
+            config::set_python_path,
+            config::get_emit_rate_cap,
+            config::set_emit_rate_cap,
+            detect::detect_python,
+            history::fork_session,
+            history::history_stats,
+            history::prune_history,
+            detached::reattach_stream,
+            memory::python_memory_usage,
+            python::last_python_command,
+            progress::get_stream_progress,
+            profiles::save_python_profile,
+            profiles::list_python_profiles,
+            profiles::load_python_profile,
+            profiles::delete_python_profile,
+            profiles::health_check_all_profiles,
+            recording::replay_python_stream,
+            streams::cancel_python_stream,
+            streams::cancel_session,
+            streams::submit_tool_result,
+            state::dump_state,
+            state::reset_state,
+            syntax::check_script_syntax,
+            tokens::count_tokens
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use process::HandlerOutput;
+    #[cfg(unix)]
+    use std::os::unix::process::ExitStatusExt;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn a_handler_dying_mid_chunk_is_reported_as_truncated() {
+        let mut child = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(r#"printf '{"type":"chunk","con'; echo 'killed' >&2; exit 1"#)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut output = HandlerOutput::Pipe(BufReader::new(child.stdout.take().unwrap()));
+        let mut stderr_lines = Some(BufReader::new(child.stderr.take().unwrap()).lines());
+
+        let line = output.next_line().await.unwrap().unwrap();
+        assert_eq!(line.text, r#"{"type":"chunk","con"#);
+        assert!(!line.terminated);
+        assert!(output.next_line().await.unwrap().is_none());
+        assert!(matches!(
+            parse::parse_line(ParseMode::Ndjson, &line.text, line.terminated),
+            Parsed::Invalid(..)
+        ));
+
+        let status = process::reap(&mut child).await.unwrap();
+        let mut stderr_tail = VecDeque::new();
+        while let Ok(Some(line)) = next_line(&mut stderr_lines).await {
+            remember_stderr(&mut stderr_tail, &line);
+        }
+        let chunk = truncated_chunk(line.text, status, stderr_tail);
+        assert_eq!(chunk.chunk_type, "truncated");
+        assert_eq!(chunk.success, Some(false));
+        assert_eq!(chunk.content.as_deref(), Some(r#"{"type":"chunk","con"#));
+        assert_eq!(chunk.exit_code, Some(1));
+        assert_eq!(chunk.error.as_deref(), Some("killed"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn a_silent_handler_times_out_and_is_killed() {
+        let mut child = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg("sleep 30; echo late")
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let mut output = HandlerOutput::Pipe(BufReader::new(child.stdout.take().unwrap()));
+        let mut child = Some(child);
+
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(100);
+        let result = tokio::select! {
+            _ = output.next_line() => panic!("the handler wrote something"),
+            _ = batch::sleep_until(Some(deadline)) => time_out_first_chunk(&mut child).await,
+        };
+        assert!(matches!(
+            result,
+            Err(CommandError::Timeout {
+                phase: TimeoutPhase::FirstChunk
+            })
+        ));
+
+        let status = child.unwrap().try_wait().unwrap().unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGKILL));
+    }
+
+    fn epoch_ms_in(offset_ms: i64) -> u64 {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        (now.as_millis() as i64 + offset_ms) as u64
+    }
+
+    #[test]
+    fn a_deadline_in_the_past_has_expired() {
+        assert!(matches!(
+            deadline_instant(epoch_ms_in(-1_000)),
+            Err(CommandError::Timeout {
+                phase: TimeoutPhase::Deadline
+            })
+        ));
+    }
+
+    #[test]
+    fn a_deadline_of_now_has_expired() {
+        assert!(deadline_instant(epoch_ms_in(0)).is_err());
+    }
+
+    #[test]
+    fn a_future_deadline_is_that_far_ahead() {
+        let before = tokio::time::Instant::now();
+        let deadline = deadline_instant(epoch_ms_in(60_000)).unwrap();
+        let ahead = deadline - before;
+        assert!(ahead > Duration::from_secs(59) && ahead <= Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,72 @@
+use serde::Serialize;
+use tauri::State;
+
+use crate::error::CommandError;
+use crate::streams::StreamRegistry;
+
+/// Resident memory of the handlers running for a session.
+#[derive(Serialize)]
+pub struct MemoryUsage {
+    pids: Vec<u32>,
+    rss_bytes: u64,
+}
+
+/// Resident set size of process `pid`, in bytes.
+#[cfg(target_os = "linux")]
+pub async fn rss_bytes(pid: u32) -> Result<u64, String> {
+    let status = tokio::fs::read_to_string(format!("/proc/{}/status", pid))
+        .await
+        .map_err(|e| format!("Failed to read memory of process {}: {}", pid, e))?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|value| value.trim().strip_suffix("kB"))
+        .and_then(|kb| kb.trim().parse::<u64>().ok())
+        .map(|kb| kb * 1024)
+        .ok_or_else(|| format!("No resident memory reported for process {}", pid))
+}
+
+/// Resident set size of process `pid`, in bytes.
+#[cfg(all(unix, not(target_os = "linux")))]
+pub async fn rss_bytes(pid: u32) -> Result<u64, String> {
+    let output = tokio::process::Command::new("ps")
+        .args(["-o", "rss=", "-p", &pid.to_string()])
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run ps: {}", e))?;
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<u64>()
+        .map(|kb| kb * 1024)
+        .map_err(|_| format!("No resident memory reported for process {}", pid))
+}
+
+/// Resident set size of process `pid`, in bytes.
+#[cfg(not(unix))]
+pub async fn rss_bytes(pid: u32) -> Result<u64, String> {
+    Err(format!(
+        "Reading the memory of process {} is not supported on this platform",
+        pid
+    ))
+}
+
+/// How much memory the handlers currently streaming for `session_id` use.
+#[tauri::command]
+pub async fn python_memory_usage(
+    registry: State<'_, StreamRegistry>,
+    session_id: String,
+) -> Result<MemoryUsage, CommandError> {
+    let pids = registry.session_pids(&session_id);
+    if pids.is_empty() {
+        return Err(format!("No handler is running for session {:?}", session_id).into());
+    }
+
+    let mut rss = 0;
+    for pid in &pids {
+        rss += rss_bytes(*pid).await?;
+    }
+    Ok(MemoryUsage {
+        pids,
+        rss_bytes: rss,
+    })
+}
@@ -0,0 +1,52 @@
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+use crate::{ChatResponse, StreamChunk};
+
+/// Rewrite `text` in NFC if it isn't already.
+fn nfc(text: &mut Option<String>) {
+    if let Some(text) = text {
+        if !is_nfc(text) {
+            *text = text.nfc().collect();
+        }
+    }
+}
+
+/// NFC-normalize every text field of a chunk.
+pub fn chunk(chunk: &mut StreamChunk) {
+    nfc(&mut chunk.content);
+    nfc(&mut chunk.error);
+    nfc(&mut chunk.message);
+    nfc(&mut chunk.reasoning);
+}
+
+/// NFC-normalize every text field of a blocking response.
+pub fn response(response: &mut ChatResponse) {
+    nfc(&mut response.message);
+    nfc(&mut response.error);
+    nfc(&mut response.warnings);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equivalent_inputs_normalize_identically() {
+        // Escaped precomposed, escaped decomposed, and literal precomposed
+        let lines = [
+            r#"{"type":"chunk","content":"caf\u00e9"}"#,
+            r#"{"type":"chunk","content":"cafe\u0301"}"#,
+            r#"{"type":"chunk","content":"café"}"#,
+        ];
+        let outputs: Vec<String> = lines
+            .iter()
+            .map(|line| {
+                let mut parsed: StreamChunk = serde_json::from_str(line).unwrap();
+                chunk(&mut parsed);
+                serde_json::to_string(&parsed).unwrap()
+            })
+            .collect();
+        assert!(outputs.iter().all(|output| *output == outputs[0]));
+        assert!(outputs[0].contains("\"content\":\"caf\u{e9}\""));
+    }
+}
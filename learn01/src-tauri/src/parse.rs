@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+
+use crate::StreamChunk;
+
+/// How the handler's stdout is framed.
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseMode {
+    /// One JSON chunk per line.
+    #[default]
+    Ndjson,
+    /// Server-sent-event style `data: {...}` lines, ending with
+    /// `data: [DONE]`; other fields and comments are ignored.
+    Sse,
+    /// Raw text; every line is reply content.
+    PlainText,
+}
+
+impl ParseMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ParseMode::Ndjson => "ndjson",
+            ParseMode::Sse => "sse",
+            ParseMode::PlainText => "plain_text",
+        }
+    }
+}
+
+/// Non-blank lines that must fail NDJSON parsing before the framing is
+/// guessed.
+const DETECT_LINES: usize = 3;
+
+/// What a line of handler output turned out to be.
+pub enum Parsed {
+    Chunk(Box<StreamChunk>),
+    /// Framing with nothing to emit.
+    Skip,
+    /// JSON that failed to parse, with the error.
+    Invalid(String, serde_json::Error),
+}
+
+pub fn parse_line(mode: ParseMode, line: &str, terminated: bool) -> Parsed {
+    match mode {
+        ParseMode::Ndjson => parse_json(line),
+        ParseMode::Sse => {
+            let Some(data) = line.strip_prefix("data:") else {
+                return Parsed::Skip;
+            };
+            let data = data.trim();
+            if data == "[DONE]" {
+                let mut done = StreamChunk::new("complete");
+                done.success = Some(true);
+                Parsed::Chunk(Box::new(done))
+            } else if data.is_empty() {
+                Parsed::Skip
+            } else {
+                parse_json(data)
+            }
+        }
+        ParseMode::PlainText => {
+            let mut chunk = StreamChunk::new("chunk");
+            chunk.content = Some(if terminated {
+                format!("{}\n", line)
+            } else {
+                line.to_string()
+            });
+            Parsed::Chunk(Box::new(chunk))
+        }
+    }
+}
+
+fn parse_json(json: &str) -> Parsed {
+    match serde_json::from_str(json) {
+        Ok(chunk) => Parsed::Chunk(Box::new(chunk)),
+        Err(e) => Parsed::Invalid(json.to_string(), e),
+    }
+}
+
+/// A line of output with what it parsed to.
+pub type ParsedLine = (String, bool, Parsed);
+
+/// Parses a stream's lines with the configured framing. With detection on,
+/// an NDJSON stream whose first lines aren't JSON is held back until it's
+/// clear whether they are SSE or plain text, then switched to that framing.
+pub struct LineParser {
+    mode: ParseMode,
+    /// Lines held while the framing is undecided; `None` once settled.
+    held: Option<Vec<(String, bool)>>,
+    switched: bool,
+}
+
+impl LineParser {
+    pub fn new(mode: ParseMode, detect: bool) -> Self {
+        Self {
+            mode,
+            held: (detect && mode == ParseMode::Ndjson).then(Vec::new),
+            switched: false,
+        }
+    }
+
+    pub fn mode(&self) -> ParseMode {
+        self.mode
+    }
+
+    /// Parse the next line. Returns the lines ready to handle: none while
+    /// the framing is undecided, all of the held ones once it is.
+    pub fn push(&mut self, line: String, terminated: bool) -> Vec<ParsedLine> {
+        let Some(held) = &mut self.held else {
+            let parsed = parse_line(self.mode, &line, terminated);
+            return vec![(line, terminated, parsed)];
+        };
+
+        let valid = !matches!(
+            parse_line(self.mode, &line, terminated),
+            Parsed::Invalid(..)
+        );
+        held.push((line, terminated));
+        let failed = held
+            .iter()
+            .filter(|(line, _)| !line.trim().is_empty())
+            .count();
+        if valid || !terminated || failed >= DETECT_LINES {
+            return self.settle(!valid);
+        }
+        Vec::new()
+    }
+
+    /// Release whatever is still held, at end of output.
+    pub fn finish(&mut self) -> Vec<ParsedLine> {
+        self.settle(true)
+    }
+
+    /// Whether the framing was switched since the last call.
+    pub fn take_switch(&mut self) -> bool {
+        std::mem::take(&mut self.switched)
+    }
+
+    /// Stop detecting, guessing the framing from the held lines unless the
+    /// stream turned out to be NDJSON after all.
+    fn settle(&mut self, guess: bool) -> Vec<ParsedLine> {
+        let lines = self.held.take().unwrap_or_default();
+        let text: Vec<&str> = lines
+            .iter()
+            .map(|(line, _)| line.trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+        // Broken JSON is still NDJSON
+        let json = text.iter().any(|line| line.starts_with(['{', '[']));
+        if guess && !text.is_empty() && !json {
+            self.mode = if text.iter().all(|line| looks_like_sse(line)) {
+                ParseMode::Sse
+            } else {
+                ParseMode::PlainText
+            };
+            self.switched = true;
+        }
+
+        lines
+            .into_iter()
+            .map(|(line, terminated)| {
+                let parsed = parse_line(self.mode, &line, terminated);
+                (line, terminated, parsed)
+            })
+            .collect()
+    }
+}
+
+fn looks_like_sse(line: &str) -> bool {
+    ["data:", "event:", "id:", "retry:", ":"]
+        .iter()
+        .any(|field| line.starts_with(field))
+}
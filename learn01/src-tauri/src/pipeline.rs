@@ -0,0 +1,555 @@
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::batch::ChunkBatcher;
+use crate::cite::CitationExtractor;
+use crate::language::{self, Language};
+use crate::limit::TokenLimit;
+use crate::rate::EmitRate;
+use crate::stop::StopMatcher;
+use crate::{StreamChunk, StreamOptions, Usage};
+
+/// Post-processing applied to chunks between the handler and the webview.
+/// Pure bookkeeping: the caller does the reading, emitting and killing.
+pub struct StreamPipeline {
+    /// First stage: turns citation markers into reference numbers.
+    citations: Option<CitationExtractor>,
+    batcher: Option<ChunkBatcher>,
+    stop: Option<StopMatcher>,
+    limit: Option<TokenLimit>,
+    /// Last stage: caps how often chunks leave the pipeline.
+    rate: Option<EmitRate>,
+    finished: bool,
+    /// Every piece of content emitted so far.
+    content: String,
+    /// Reasoning text, kept apart from the reply.
+    reasoning: String,
+    /// Usage reported by the handler, folded into the final chunk.
+    usage: Option<Usage>,
+    /// The previous chunk's content, when it was a content chunk and
+    /// `dedup_consecutive` is set.
+    last_content: Option<String>,
+    dedup: bool,
+    /// Content to wrap the reply in, until it has been emitted.
+    prefix: Option<String>,
+    suffix: Option<String>,
+    /// Whether the reply's language is still to be detected.
+    detect_language: bool,
+    language: Option<Language>,
+}
+
+impl StreamPipeline {
+    pub fn new(
+        options: &StreamOptions,
+        limit: Option<TokenLimit>,
+        max_emits_per_sec: Option<u32>,
+    ) -> Self {
+        Self {
+            citations: options.extract_citations.then(CitationExtractor::default),
+            batcher: options
+                .batch_interval_ms
+                .map(|ms| ChunkBatcher::new(Duration::from_millis(ms))),
+            stop: StopMatcher::new(&options.stop_sequences),
+            limit,
+            rate: max_emits_per_sec.map(EmitRate::new),
+            finished: false,
+            content: String::new(),
+            reasoning: String::new(),
+            usage: None,
+            last_content: None,
+            dedup: options.dedup_consecutive,
+            prefix: options.content_prefix.clone(),
+            suffix: options.content_suffix.clone(),
+            detect_language: options.detect_language,
+            language: None,
+        }
+    }
+
+    /// Process a chunk parsed from the handler. Returns the chunks to emit now.
+    pub fn push(&mut self, chunk: StreamChunk) -> Vec<StreamChunk> {
+        let ready = self.process(chunk);
+        self.throttle(ready)
+    }
+
+    fn process(&mut self, mut chunk: StreamChunk) -> Vec<StreamChunk> {
+        let mut ready = Vec::new();
+        if self.dedup {
+            let text = chunk.content.as_deref().unwrap_or_default();
+            if !chunk.is_content() {
+                self.last_content = None;
+            } else if self.last_content.as_deref() == Some(text) {
+                tracing::warn!(content = ?chunk.content, "dropped a repeated content chunk");
+                return ready;
+            } else {
+                self.last_content = Some(text.to_string());
+            }
+        }
+        if chunk.is_content() {
+            let cited = match &mut self.citations {
+                Some(citations) => {
+                    let (text, cited) =
+                        citations.push(chunk.content.as_deref().unwrap_or_default());
+                    // All of it might be part of a marker
+                    if text.is_empty() {
+                        return ready;
+                    }
+                    chunk.content = Some(text);
+                    cited
+                }
+                None => Vec::new(),
+            };
+            self.enforce_limits(chunk, &mut ready);
+            for citation in cited {
+                let mut chunk = StreamChunk::new("citation");
+                chunk.citation = Some(citation);
+                self.batch(chunk, &mut ready);
+            }
+            return ready;
+        }
+
+        // Side-channel chunks like `warning` or `memory` pass held text by;
+        // releasing it for them would split stop sequences whenever the
+        // handler logs something. An error is as good as the end, though
+        if chunk.is_terminal() || chunk.chunk_type == "error" {
+            self.release_held(&mut ready);
+        }
+        if self.finished {
+            return ready;
+        }
+        match chunk.chunk_type.as_str() {
+            "reasoning" => self
+                .reasoning
+                .push_str(chunk.content.as_deref().unwrap_or_default()),
+            "usage" => match &chunk.usage {
+                Some(usage) if usage.is_valid() => self.usage = Some(usage.clone()),
+                _ => {
+                    tracing::warn!("dropping usage chunk with missing or negative values");
+                    return ready;
+                }
+            },
+            "complete" => {
+                self.close(&mut ready);
+                self.conclude(&mut chunk);
+            }
+            _ => {}
+        }
+
+        self.batch(chunk, &mut ready);
+        ready
+    }
+
+    /// Run content through the stop matcher and token limit, ending the
+    /// stream if either says so.
+    fn enforce_limits(&mut self, mut chunk: StreamChunk, ready: &mut Vec<StreamChunk>) {
+        self.open(ready);
+        if self.stop.is_none() && self.limit.is_none() {
+            self.batch(chunk, ready);
+            return;
+        }
+
+        let mut text = chunk.content.take().unwrap_or_default();
+        let mut finish_reason = None;
+        if let Some(stop) = &mut self.stop {
+            let stopped;
+            (text, stopped) = stop.push(&text);
+            if stopped {
+                finish_reason = Some("stop");
+            }
+        }
+        if let Some(limit) = &mut self.limit {
+            let reached;
+            (text, reached) = limit.push(&text);
+            if reached {
+                finish_reason = Some("length");
+            }
+        }
+
+        if !text.is_empty() {
+            chunk.content = Some(text);
+            self.batch(chunk, ready);
+        }
+        if let Some(reason) = finish_reason {
+            self.end(reason, ready);
+        }
+    }
+
+    /// True once the pipeline has ended the stream on its own (a stop
+    /// sequence matched or `max_tokens` was reached); the child should be
+    /// killed.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// The full content emitted so far.
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// Bytes of content held back by the stop matcher and by the batcher.
+    pub fn held(&self) -> (usize, usize) {
+        (
+            self.stop.as_ref().map_or(0, StopMatcher::held_len),
+            self.batcher.as_ref().map_or(0, ChunkBatcher::pending_len),
+        )
+    }
+
+    /// When `flush` should next be called, if anything is waiting.
+    pub fn deadline(&self) -> Option<Instant> {
+        let batch = self.batcher.as_ref().and_then(ChunkBatcher::deadline);
+        let rate = self.rate.as_ref().and_then(EmitRate::deadline);
+        match (batch, rate) {
+            (Some(batch), Some(rate)) => Some(batch.min(rate)),
+            (batch, rate) => batch.or(rate),
+        }
+    }
+
+    /// Emit whatever is pending in the current batch, and whatever the emit
+    /// rate cap now lets through.
+    pub fn flush(&mut self) -> Vec<StreamChunk> {
+        let mut ready = Vec::new();
+        if self
+            .batcher
+            .as_ref()
+            .and_then(ChunkBatcher::deadline)
+            .is_some_and(|deadline| deadline <= Instant::now())
+        {
+            self.flush_into(&mut ready);
+        }
+        match &mut self.rate {
+            Some(rate) => {
+                let mut released = rate.flush();
+                released.extend(rate.push(ready));
+                released
+            }
+            None => ready,
+        }
+    }
+
+    /// Drain everything still held back, at end of stream or on cancel.
+    pub fn finish(&mut self) -> Vec<StreamChunk> {
+        let mut ready = Vec::new();
+        self.release_held(&mut ready);
+        self.drain(ready)
+    }
+
+    /// Emit the pending batch and whatever the emit rate cap holds, as the
+    /// machine is about to sleep. Text a stop sequence may still claim stays
+    /// held, since the stream carries on.
+    pub fn checkpoint(&mut self) -> Vec<StreamChunk> {
+        self.drain(Vec::new())
+    }
+
+    fn drain(&mut self, mut ready: Vec<StreamChunk>) -> Vec<StreamChunk> {
+        self.flush_into(&mut ready);
+        match &mut self.rate {
+            Some(rate) => {
+                let mut held = rate.finish();
+                held.extend(ready);
+                held
+            }
+            None => ready,
+        }
+    }
+
+    /// Hold back chunks over the emit rate cap.
+    fn throttle(&mut self, ready: Vec<StreamChunk>) -> Vec<StreamChunk> {
+        match &mut self.rate {
+            Some(rate) => rate.push(ready),
+            None => ready,
+        }
+    }
+
+    /// End the stream early with a synthesized `complete` chunk.
+    fn end(&mut self, finish_reason: &str, ready: &mut Vec<StreamChunk>) {
+        self.close(ready);
+        self.flush_into(ready);
+        let mut done = StreamChunk::new("complete");
+        done.success = Some(true);
+        done.finish_reason = Some(finish_reason.to_string());
+        self.conclude(&mut done);
+        ready.push(done);
+        self.finished = true;
+    }
+
+    /// Emit `content_prefix` ahead of the first content.
+    fn open(&mut self, ready: &mut Vec<StreamChunk>) {
+        if let Some(prefix) = self.prefix.take() {
+            let mut chunk = StreamChunk::new("chunk");
+            chunk.content = Some(prefix);
+            self.batch(chunk, ready);
+        }
+    }
+
+    /// Emit `content_suffix` after the last content, opening first if the
+    /// reply was empty.
+    fn close(&mut self, ready: &mut Vec<StreamChunk>) {
+        self.open(ready);
+        if let Some(suffix) = self.suffix.take() {
+            let mut chunk = StreamChunk::new("chunk");
+            chunk.content = Some(suffix);
+            self.batch(chunk, ready);
+        }
+    }
+
+    /// Attach the accumulated texts, usage and language to the stream's
+    /// final chunk. A reply too short to have been sampled is detected now.
+    fn conclude(&mut self, done: &mut StreamChunk) {
+        if std::mem::take(&mut self.detect_language) {
+            self.language = language::detect(&self.content);
+        }
+        done.language = self.language.clone();
+        if let Some(citations) = &self.citations {
+            done.citations = Some(citations.citations().to_vec());
+        }
+        done.message = Some(self.content.clone());
+        if !self.reasoning.is_empty() {
+            done.reasoning = Some(self.reasoning.clone());
+        }
+        if self.usage.is_some() {
+            done.usage = self.usage.clone();
+        }
+    }
+
+    fn batch(&mut self, chunk: StreamChunk, ready: &mut Vec<StreamChunk>) {
+        if chunk.is_content() {
+            self.content
+                .push_str(chunk.content.as_deref().unwrap_or_default());
+        }
+        match &mut self.batcher {
+            Some(batcher) => ready.extend(batcher.push(chunk)),
+            None => ready.push(chunk),
+        }
+        if self.detect_language && self.content.len() >= language::SAMPLE_BYTES {
+            self.detect(ready);
+        }
+    }
+
+    /// Emit the reply's language, detected once on the content so far.
+    fn detect(&mut self, ready: &mut Vec<StreamChunk>) {
+        self.detect_language = false;
+        self.language = language::detect(&self.content);
+        if let Some(language) = &self.language {
+            let mut chunk = StreamChunk::new("language");
+            chunk.language = Some(language.clone());
+            self.batch(chunk, ready);
+        }
+    }
+
+    fn flush_into(&mut self, ready: &mut Vec<StreamChunk>) {
+        if let Some(chunk) = self.batcher.as_mut().and_then(ChunkBatcher::flush) {
+            ready.push(chunk);
+        }
+    }
+
+    /// Text withheld by the citation extractor and the stop matcher goes out
+    /// before a terminal or error chunk, and at the end of the stream.
+    fn release_held(&mut self, ready: &mut Vec<StreamChunk>) {
+        let unfinished = self
+            .citations
+            .as_mut()
+            .map(CitationExtractor::finish)
+            .unwrap_or_default();
+        if !unfinished.is_empty() {
+            let mut chunk = StreamChunk::new("chunk");
+            chunk.content = Some(unfinished);
+            self.enforce_limits(chunk, ready);
+        }
+
+        let Some(stop) = &mut self.stop else {
+            return;
+        };
+        let mut text = stop.finish();
+        let mut reached = false;
+        if let Some(limit) = &mut self.limit {
+            (text, reached) = limit.push(&text);
+        }
+        if !text.is_empty() {
+            let mut chunk = StreamChunk::new("chunk");
+            chunk.content = Some(text);
+            self.batch(chunk, ready);
+        }
+        if reached {
+            self.end("length", ready);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content(text: &str) -> StreamChunk {
+        let mut chunk = StreamChunk::new("chunk");
+        chunk.content = Some(text.to_string());
+        chunk
+    }
+
+    fn summary(chunks: &[StreamChunk]) -> Vec<(String, Option<String>)> {
+        chunks
+            .iter()
+            .map(|chunk| (chunk.chunk_type.clone(), chunk.content.clone()))
+            .collect()
+    }
+
+    fn batched() -> StreamPipeline {
+        let options = StreamOptions {
+            batch_interval_ms: Some(60_000),
+            ..Default::default()
+        };
+        StreamPipeline::new(&options, None, None)
+    }
+
+    #[test]
+    fn batching_keeps_content_in_order_around_other_chunks() {
+        let mut pipeline = batched();
+        let mut emitted = Vec::new();
+        emitted.extend(pipeline.push(content("one ")));
+        emitted.extend(pipeline.push(content("two ")));
+        emitted.extend(pipeline.push(StreamChunk::new("warning")));
+        emitted.extend(pipeline.push(content("three")));
+        emitted.extend(pipeline.finish());
+        assert_eq!(
+            summary(&emitted),
+            [
+                ("chunk".to_string(), Some("one two ".to_string())),
+                ("warning".to_string(), None),
+                ("chunk".to_string(), Some("three".to_string())),
+            ]
+        );
+        assert_eq!(pipeline.content(), "one two three");
+    }
+
+    #[test]
+    fn finish_releases_the_pending_batch() {
+        let mut pipeline = batched();
+        assert!(pipeline.push(content("pending")).is_empty());
+        assert_eq!(pipeline.held(), (0, 7));
+        assert_eq!(
+            summary(&pipeline.finish()),
+            [("chunk".to_string(), Some("pending".to_string()))]
+        );
+        assert_eq!(pipeline.held(), (0, 0));
+    }
+
+    #[test]
+    fn checkpoint_keeps_held_stop_text() {
+        let options = StreamOptions {
+            batch_interval_ms: Some(60_000),
+            stop_sequences: vec!["STOP".to_string()],
+            ..Default::default()
+        };
+        let mut pipeline = StreamPipeline::new(&options, None, None);
+        assert!(pipeline.push(content("almost ST")).is_empty());
+        assert_eq!(
+            summary(&pipeline.checkpoint()),
+            [("chunk".to_string(), Some("almost ".to_string()))]
+        );
+        assert_eq!(pipeline.held(), (2, 0));
+        assert!(pipeline
+            .push(content("OP"))
+            .iter()
+            .any(StreamChunk::is_terminal));
+    }
+
+    fn stopping_at(stop: &str) -> StreamPipeline {
+        let options = StreamOptions {
+            stop_sequences: vec![stop.to_string()],
+            ..Default::default()
+        };
+        StreamPipeline::new(&options, None, None)
+    }
+
+    #[test]
+    fn side_channel_chunks_dont_split_a_stop_sequence() {
+        let mut pipeline = stopping_at("STOP");
+        let mut emitted = Vec::new();
+        emitted.extend(pipeline.push(content("before ST")));
+        emitted.extend(pipeline.push(StreamChunk::new("warning")));
+        emitted.extend(pipeline.push(StreamChunk::new("memory")));
+        emitted.extend(pipeline.push(content("OP after")));
+        assert!(pipeline.is_finished());
+        assert_eq!(pipeline.content(), "before ");
+        let types: Vec<&str> = emitted.iter().map(|c| c.chunk_type.as_str()).collect();
+        assert_eq!(types, ["chunk", "warning", "memory", "complete"]);
+        assert_eq!(emitted[3].finish_reason.as_deref(), Some("stop"));
+    }
+
+    #[test]
+    fn held_text_goes_out_before_complete() {
+        let mut pipeline = stopping_at("STOP");
+        let mut emitted = pipeline.push(content("almost ST"));
+        emitted.extend(pipeline.push(StreamChunk::new("complete")));
+        assert_eq!(
+            summary(&emitted),
+            [
+                ("chunk".to_string(), Some("almost ".to_string())),
+                ("chunk".to_string(), Some("ST".to_string())),
+                ("complete".to_string(), None),
+            ]
+        );
+        assert_eq!(emitted[2].message.as_deref(), Some("almost ST"));
+    }
+
+    #[test]
+    fn held_text_goes_out_before_an_error() {
+        let mut pipeline = stopping_at("STOP");
+        let mut emitted = pipeline.push(content("almost ST"));
+        emitted.extend(pipeline.push(StreamChunk::new("error")));
+        emitted.extend(pipeline.finish());
+        assert_eq!(
+            summary(&emitted),
+            [
+                ("chunk".to_string(), Some("almost ".to_string())),
+                ("chunk".to_string(), Some("ST".to_string())),
+                ("error".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn dedup_collapses_only_consecutive_repeats() {
+        let options = StreamOptions {
+            dedup_consecutive: true,
+            ..Default::default()
+        };
+        let mut pipeline = StreamPipeline::new(&options, None, None);
+        let mut emitted = Vec::new();
+        emitted.extend(pipeline.push(content("again")));
+        emitted.extend(pipeline.push(content("again")));
+        emitted.extend(pipeline.push(StreamChunk::new("warning")));
+        emitted.extend(pipeline.push(content("again")));
+        assert_eq!(
+            summary(&emitted),
+            [
+                ("chunk".to_string(), Some("again".to_string())),
+                ("warning".to_string(), None),
+                ("chunk".to_string(), Some("again".to_string())),
+            ]
+        );
+        assert_eq!(pipeline.content(), "againagain");
+    }
+
+    #[test]
+    fn a_warning_inside_a_citation_marker_doesnt_break_it() {
+        let options = StreamOptions {
+            extract_citations: true,
+            ..Default::default()
+        };
+        let mut pipeline = StreamPipeline::new(&options, None, None);
+        let mut emitted = Vec::new();
+        emitted.extend(pipeline.push(content("see [[cite:do")));
+        emitted.extend(pipeline.push(StreamChunk::new("warning")));
+        emitted.extend(pipeline.push(content("c1]] here")));
+        assert_eq!(
+            summary(&emitted),
+            [
+                ("chunk".to_string(), Some("see ".to_string())),
+                ("warning".to_string(), None),
+                ("chunk".to_string(), Some("[1] here".to_string())),
+                ("citation".to_string(), None),
+            ]
+        );
+        let citation = emitted[3].citation.as_ref().unwrap();
+        assert_eq!((citation.number, citation.doc_id.as_str()), (1, "doc1"));
+    }
+}
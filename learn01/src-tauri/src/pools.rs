@@ -0,0 +1,515 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command as AsyncCommand};
+use tokio::sync::Semaphore;
+
+use crate::config::{ConfigState, PythonConfig};
+use crate::error::CommandError;
+use crate::python;
+
+/// How often pools are checked against `idle_timeout_secs`.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a stopping worker gets to exit on its own before it is killed.
+const STOP_GRACE: Duration = Duration::from_secs(2);
+
+/// How a pool's workers are launched.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PoolConfig {
+    /// Worker script, run with `--worker`; the chat handler when unset.
+    pub script: Option<PathBuf>,
+    /// Interpreter override; the app's configured interpreter when unset.
+    pub interpreter: Option<PathBuf>,
+    /// Most workers the pool runs at once.
+    pub size: usize,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            script: None,
+            interpreter: None,
+            size: 1,
+        }
+    }
+}
+
+/// A long-lived handler process answering one JSON line with another.
+struct Worker {
+    // Held so the process is killed when the worker is dropped
+    _child: Child,
+    /// The pool's `generation` when the worker was started.
+    generation: u64,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+}
+
+impl Worker {
+    async fn spawn(
+        app: &AppHandle,
+        python: &PythonConfig,
+        script: &PathBuf,
+        generation: u64,
+    ) -> Result<Self, String> {
+        let mut command = AsyncCommand::from(python::command(app, python)?);
+        command
+            .arg(script)
+            .arg("--worker")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            // Nothing drains a long-lived worker's stderr; let it through
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true);
+        let mut child = python::spawn(app, python, &mut command)
+            .map_err(|e| format!("Failed to start worker: {}", e))?;
+        tracing::info!(pid = child.id(), ?script, "spawned worker");
+
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| "Failed to open worker stdin".to_string())?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| "Failed to capture worker stdout".to_string())?;
+        Ok(Self {
+            _child: child,
+            generation,
+            stdin,
+            stdout: BufReader::new(stdout).lines(),
+        })
+    }
+
+    /// Close stdin so the worker's request loop ends, then kill it if it
+    /// hasn't exited within the grace period.
+    async fn stop(self) {
+        let Self {
+            _child: mut child,
+            stdin,
+            ..
+        } = self;
+        drop(stdin);
+        if tokio::time::timeout(STOP_GRACE, child.wait())
+            .await
+            .is_err()
+        {
+            tracing::warn!(pid = child.id(), "worker ignored shutdown, killing it");
+        }
+    }
+
+    async fn request(&mut self, line: &str) -> Result<String, String> {
+        self.stdin
+            .write_all(line.as_bytes())
+            .await
+            .map_err(|e| format!("Failed to send request to worker: {}", e))?;
+        self.stdout
+            .next_line()
+            .await
+            .map_err(|e| format!("Failed to read worker response: {}", e))?
+            .ok_or_else(|| "Worker exited before responding".to_string())
+    }
+}
+
+struct Pool {
+    /// For launching workers, which resolve the interpreter through app state.
+    app: AppHandle,
+    config: PoolConfig,
+    /// App settings at the time the pool was created or last reconfigured.
+    python: Mutex<PythonConfig>,
+    /// Bumped whenever `python` changes, so older workers are retired.
+    generation: AtomicU64,
+    script: PathBuf,
+    idle: Mutex<Vec<Worker>>,
+    /// One permit per worker the pool may run; closed on shutdown.
+    slots: Semaphore,
+    /// Models pinned with `load_model`, as last reported by the workers.
+    /// New workers load them before serving anything.
+    models: Mutex<Vec<String>>,
+    /// When a worker last finished a request.
+    last_used: Mutex<Instant>,
+}
+
+/// A worker's answer to a control message.
+#[derive(Deserialize)]
+struct ControlReply {
+    success: bool,
+    error: Option<String>,
+    /// Why a load failed, e.g. `out_of_memory` or `missing_weights`.
+    reason: Option<String>,
+    #[serde(default)]
+    loaded_models: Vec<String>,
+}
+
+fn control_line(request_type: &str, model_id: &str) -> Result<String, String> {
+    let request = serde_json::json!({ "type": request_type, "model_id": model_id });
+    let mut line =
+        serde_json::to_string(&request).map_err(|e| format!("Invalid request: {}", e))?;
+    line.push('\n');
+    Ok(line)
+}
+
+fn parse_reply(response: &str) -> Result<ControlReply, String> {
+    serde_json::from_str(response).map_err(|e| format!("Failed to parse worker response: {}", e))
+}
+
+impl Pool {
+    /// Hand `line` to an idle worker, spawning one if the pool has room, and
+    /// wait for its response.
+    async fn send(&self, line: &str) -> Result<String, String> {
+        let _slot = self
+            .slots
+            .acquire()
+            .await
+            .map_err(|_| "Worker pool was shut down".to_string())?;
+
+        let idle = self.idle.lock().unwrap().pop();
+        let reused = idle.is_some();
+        let mut worker = match idle {
+            Some(worker) => worker,
+            None => self.spawn_worker().await?,
+        };
+
+        // A failed worker is dropped, which kills it
+        let response = match worker.request(line).await {
+            Ok(response) => response,
+            // An idle worker may have died since its last request
+            Err(e) if reused => {
+                tracing::warn!("idle worker failed, respawning: {}", e);
+                worker = self.spawn_worker().await?;
+                worker.request(line).await?
+            }
+            Err(e) => return Err(e),
+        };
+
+        self.release(worker);
+        Ok(response)
+    }
+
+    /// Send `line` to every worker in the pool, once none are busy, starting
+    /// one if there are none yet. Returns each worker's response.
+    async fn broadcast(&self, line: &str) -> Result<Vec<String>, String> {
+        let _slots = self
+            .slots
+            .acquire_many(self.config.size as u32)
+            .await
+            .map_err(|_| "Worker pool was shut down".to_string())?;
+
+        let mut workers: Vec<Worker> = self.idle.lock().unwrap().drain(..).collect();
+        if workers.is_empty() {
+            workers.push(self.spawn_worker().await?);
+        }
+
+        let mut responses = Ok(Vec::new());
+        for mut worker in workers {
+            match worker.request(line).await {
+                Ok(response) => {
+                    if let Ok(responses) = &mut responses {
+                        responses.push(response);
+                    }
+                    self.release(worker);
+                }
+                Err(e) => responses = Err(e),
+            }
+        }
+        responses
+    }
+
+    /// Start a worker with the pool's pinned models already loaded.
+    async fn spawn_worker(&self) -> Result<Worker, String> {
+        let python = self.python.lock().unwrap().clone();
+        let generation = self.generation.load(Ordering::SeqCst);
+        let mut worker = Worker::spawn(&self.app, &python, &self.script, generation).await?;
+        let models = self.models.lock().unwrap().clone();
+        for model_id in models {
+            let line = control_line("load_model", &model_id)?;
+            let reply = parse_reply(&worker.request(&line).await?)?;
+            if !reply.success {
+                tracing::warn!(model_id, error = ?reply.error, "new worker failed to load pinned model");
+            }
+        }
+        Ok(worker)
+    }
+
+    /// Return a worker to the idle list, unless the pool is shutting down or
+    /// has been reconfigured since the worker started.
+    fn release(&self, worker: Worker) {
+        *self.last_used.lock().unwrap() = Instant::now();
+        let current = worker.generation == self.generation.load(Ordering::SeqCst);
+        if current && !self.slots.is_closed() {
+            self.idle.lock().unwrap().push(worker);
+        }
+    }
+
+    /// Send a model control message to every worker and record the models
+    /// they report as loaded.
+    async fn control(&self, request_type: &str, model_id: &str) -> Result<(), CommandError> {
+        let line = control_line(request_type, model_id)?;
+        for response in self.broadcast(&line).await? {
+            let reply = parse_reply(&response)?;
+            if !reply.success && request_type != "load_model" {
+                let error = reply.error.unwrap_or_default();
+                return Err(format!("Failed to unload model '{}': {}", model_id, error).into());
+            }
+            if !reply.success {
+                return Err(CommandError::ModelLoadFailed {
+                    model_id: model_id.to_string(),
+                    reason: reply.reason.unwrap_or_else(|| "unknown".to_string()),
+                    message: reply.error.unwrap_or_default(),
+                });
+            }
+            *self.models.lock().unwrap() = reply.loaded_models;
+        }
+        Ok(())
+    }
+
+    /// Refuse new requests and hand over the idle workers to be stopped;
+    /// busy ones stop once their request is answered.
+    fn shutdown(&self) -> Vec<Worker> {
+        self.slots.close();
+        self.idle.lock().unwrap().drain(..).collect()
+    }
+}
+
+/// Named pools of persistent workers, each with its own script and size.
+#[derive(Default)]
+pub struct WorkerPools {
+    pools: Mutex<HashMap<String, Arc<Pool>>>,
+}
+
+impl WorkerPools {
+    fn get(&self, name: &str) -> Result<Arc<Pool>, String> {
+        self.pools
+            .lock()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("Unknown worker pool: {:?}", name))
+    }
+
+    fn shutdown(&self, name: &str) -> Option<Vec<Worker>> {
+        let pool = self.pools.lock().unwrap().remove(name)?;
+        Some(pool.shutdown())
+    }
+
+    /// Take the idle workers of every pool unused for `timeout`. The pools
+    /// stay registered and respawn workers on their next request.
+    fn take_idle(&self, timeout: Duration) -> Vec<(String, Vec<Worker>)> {
+        let pools = self.pools.lock().unwrap();
+        pools
+            .iter()
+            .filter(|(_, pool)| pool.last_used.lock().unwrap().elapsed() >= timeout)
+            .map(|(name, pool)| {
+                let workers: Vec<Worker> = pool.idle.lock().unwrap().drain(..).collect();
+                (name.clone(), workers)
+            })
+            .filter(|(_, workers)| !workers.is_empty())
+            .collect()
+    }
+
+    /// Apply new app settings to every pool, keeping each pool's interpreter
+    /// override. Returns the idle workers to stop; busy ones are retired once
+    /// they finish, and replacements start on demand.
+    fn reconfigure(&self, python: &PythonConfig) -> Vec<Worker> {
+        let pools = self.pools.lock().unwrap();
+        let mut stale = Vec::new();
+        for pool in pools.values() {
+            let mut settings = python.clone();
+            if pool.config.interpreter.is_some() {
+                settings.interpreter = pool.config.interpreter.clone();
+            }
+            *pool.python.lock().unwrap() = settings;
+            pool.generation.fetch_add(1, Ordering::SeqCst);
+            stale.extend(pool.idle.lock().unwrap().drain(..));
+        }
+        stale
+    }
+
+    /// Restart every pool's workers with new app settings.
+    pub async fn restart_workers(&self, python: &PythonConfig) {
+        let count = stop_workers(self.reconfigure(python)).await;
+        tracing::info!(workers = count, "stopped workers to apply new settings");
+    }
+
+    /// Idle workers of each pool, by pool name.
+    pub fn idle_workers(&self) -> BTreeMap<String, usize> {
+        let pools = self.pools.lock().unwrap();
+        pools
+            .iter()
+            .map(|(name, pool)| (name.clone(), pool.idle.lock().unwrap().len()))
+            .collect()
+    }
+
+    /// Shut every pool down. Returns how many workers were stopped.
+    pub async fn shutdown_all(&self) -> usize {
+        let pools: Vec<_> = self.pools.lock().unwrap().drain().collect();
+        let idle = pools.iter().flat_map(|(_, pool)| pool.shutdown()).collect();
+        stop_workers(idle).await
+    }
+}
+
+/// Stop `workers` one at a time, each given `STOP_GRACE` to exit. Returns
+/// how many there were.
+async fn stop_workers(workers: Vec<Worker>) -> usize {
+    let count = workers.len();
+    for worker in workers {
+        worker.stop().await;
+    }
+    count
+}
+
+#[derive(Clone, Serialize)]
+struct IdleShutdown {
+    pool: String,
+    workers: usize,
+}
+
+/// Background task stopping the workers of pools left idle for longer than
+/// `idle_timeout_secs`, emitting `worker-idle-shutdown` for each pool.
+pub async fn stop_idle_workers(app: AppHandle) {
+    let mut interval = tokio::time::interval(IDLE_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let Some(secs) = app.state::<ConfigState>().get().idle_timeout_secs else {
+            continue;
+        };
+
+        let idle = app
+            .state::<WorkerPools>()
+            .take_idle(Duration::from_secs(secs));
+        for (pool, workers) in idle {
+            let count = stop_workers(workers).await;
+            tracing::info!(pool, workers = count, "stopped idle workers");
+            let payload = IdleShutdown {
+                pool,
+                workers: count,
+            };
+            if let Err(e) = app.emit("worker-idle-shutdown", payload) {
+                tracing::warn!("failed to emit worker-idle-shutdown: {}", e);
+            }
+        }
+    }
+}
+
+/// Create the pool `name` unless it already exists with the same config.
+/// Workers are started on demand, not here.
+#[tauri::command]
+pub fn ensure_pool(
+    app: AppHandle,
+    pools: State<'_, WorkerPools>,
+    app_config: State<'_, ConfigState>,
+    name: String,
+    config: Option<PoolConfig>,
+) -> Result<(), CommandError> {
+    let config = config.unwrap_or_default();
+    if config.size == 0 {
+        return Err("Worker pool size must be at least 1".to_string().into());
+    }
+
+    let mut pools = pools.pools.lock().unwrap();
+    if let Some(existing) = pools.get(&name) {
+        if existing.config == config {
+            return Ok(());
+        }
+        return Err(format!(
+            "Worker pool {:?} already exists with a different config; shut it down first",
+            name
+        )
+        .into());
+    }
+
+    let script = match &config.script {
+        Some(script) => script.clone(),
+        None => python::handler_script()?,
+    };
+    if !script.exists() {
+        return Err(format!("Worker script not found at: {:?}", script).into());
+    }
+
+    let mut python = app_config.get();
+    if config.interpreter.is_some() {
+        python.interpreter = config.interpreter.clone();
+    }
+    pools.insert(
+        name,
+        Arc::new(Pool {
+            app,
+            slots: Semaphore::new(config.size),
+            config,
+            python: Mutex::new(python),
+            generation: AtomicU64::new(0),
+            script,
+            idle: Mutex::new(Vec::new()),
+            models: Mutex::new(Vec::new()),
+            last_used: Mutex::new(Instant::now()),
+        }),
+    );
+    Ok(())
+}
+
+/// Send one JSON request to a worker in the pool and return its response.
+#[tauri::command]
+pub async fn send_to_pool(
+    pools: State<'_, WorkerPools>,
+    name: String,
+    request: serde_json::Value,
+) -> Result<serde_json::Value, CommandError> {
+    let pool = pools.get(&name)?;
+    let mut line =
+        serde_json::to_string(&request).map_err(|e| format!("Invalid request: {}", e))?;
+    line.push('\n');
+
+    let response = pool.send(&line).await?;
+    Ok(serde_json::from_str(&response)
+        .map_err(|e| format!("Failed to parse worker response: {}", e))?)
+}
+
+/// Stop the pool's workers and forget it. Returns how many idle workers
+/// were stopped; busy ones finish their current request first.
+#[tauri::command]
+pub async fn shutdown_pool(
+    pools: State<'_, WorkerPools>,
+    name: String,
+) -> Result<usize, CommandError> {
+    let idle = pools
+        .shutdown(&name)
+        .ok_or_else(|| format!("Unknown worker pool: {:?}", name))?;
+    Ok(stop_workers(idle).await)
+}
+
+/// Load `model_id` in every worker of the pool and keep it resident,
+/// including in workers started later.
+#[tauri::command]
+pub async fn load_model(
+    pools: State<'_, WorkerPools>,
+    pool: String,
+    model_id: String,
+) -> Result<(), CommandError> {
+    pools.get(&pool)?.control("load_model", &model_id).await
+}
+
+/// Evict `model_id` from every worker of the pool.
+#[tauri::command]
+pub async fn unload_model(
+    pools: State<'_, WorkerPools>,
+    pool: String,
+    model_id: String,
+) -> Result<(), CommandError> {
+    pools.get(&pool)?.control("unload_model", &model_id).await
+}
+
+/// Models the pool's workers report as loaded.
+#[tauri::command]
+pub fn loaded_models(
+    pools: State<'_, WorkerPools>,
+    pool: String,
+) -> Result<Vec<String>, CommandError> {
+    Ok(pools.get(&pool)?.models.lock().unwrap().clone())
+}
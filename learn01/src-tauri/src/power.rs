@@ -0,0 +1,232 @@
+use tokio::sync::mpsc::UnboundedSender;
+
+/// What the OS says about sleep. Where it says nothing, sleep is only
+/// noticed afterwards, from the clocks in `sleep`.
+pub enum PowerEvent {
+    /// The machine is about to sleep. It holds off until `delay` is
+    /// dropped, for as long as the platform allows.
+    Suspending {
+        delay: SleepDelay,
+    },
+    Resumed,
+}
+
+/// Keeps the machine awake while alive.
+pub struct SleepDelay {
+    _held: Box<dyn Send>,
+}
+
+/// Longest a suspend is held off for; well within what every platform
+/// grants (two seconds on Windows).
+#[cfg(any(target_os = "macos", windows))]
+const MAX_DELAY: std::time::Duration = std::time::Duration::from_millis(1500);
+
+/// Hand `events` a suspend and wait until its delay is dropped.
+#[cfg(any(target_os = "macos", windows))]
+fn suspend_and_wait(events: &UnboundedSender<PowerEvent>) {
+    let (ready, waiting) = std::sync::mpsc::channel::<()>();
+    let delay = SleepDelay {
+        _held: Box::new(ready),
+    };
+    if events.send(PowerEvent::Suspending { delay }).is_ok() {
+        // Disconnects as soon as the delay is dropped
+        let _ = waiting.recv_timeout(MAX_DELAY);
+    }
+}
+
+/// Start sending `events` as the OS reports them, for the rest of the app.
+#[cfg(target_os = "linux")]
+pub fn subscribe(events: UnboundedSender<PowerEvent>) {
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = logind::listen(events).await {
+            tracing::info!("no sleep notifications from logind: {}", e);
+        }
+    });
+}
+
+#[cfg(target_os = "linux")]
+mod logind {
+    use futures_util::StreamExt;
+
+    use super::{PowerEvent, SleepDelay};
+
+    #[zbus::proxy(
+        interface = "org.freedesktop.login1.Manager",
+        default_service = "org.freedesktop.login1",
+        default_path = "/org/freedesktop/login1"
+    )]
+    trait Manager {
+        fn inhibit(
+            &self,
+            what: &str,
+            who: &str,
+            why: &str,
+            mode: &str,
+        ) -> zbus::Result<zbus::zvariant::OwnedFd>;
+
+        #[zbus(signal)]
+        fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+    }
+
+    /// logind waits for a delay lock to be released before suspending, so
+    /// one is held throughout, except while asleep.
+    async fn delay_sleep(manager: &ManagerProxy<'_>) -> Option<zbus::zvariant::OwnedFd> {
+        let why = "Save what running streams generated";
+        match manager.inhibit("sleep", "learn01", why, "delay").await {
+            Ok(lock) => Some(lock),
+            Err(e) => {
+                tracing::info!("can't delay sleep: {}", e);
+                None
+            }
+        }
+    }
+
+    pub async fn listen(
+        events: tokio::sync::mpsc::UnboundedSender<PowerEvent>,
+    ) -> zbus::Result<()> {
+        let connection = zbus::Connection::system().await?;
+        let manager = ManagerProxy::new(&connection).await?;
+        let mut signals = manager.receive_prepare_for_sleep().await?;
+        let mut lock = delay_sleep(&manager).await;
+        while let Some(signal) = signals.next().await {
+            let event = if signal.args()?.start {
+                PowerEvent::Suspending {
+                    delay: SleepDelay {
+                        _held: Box::new(lock.take()),
+                    },
+                }
+            } else {
+                lock = delay_sleep(&manager).await;
+                PowerEvent::Resumed
+            };
+            if events.send(event).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+pub fn subscribe(events: UnboundedSender<PowerEvent>) {
+    use std::ffi::c_void;
+    use windows_sys::Win32::System::Power::{
+        PowerRegisterSuspendResumeNotification, DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        DEVICE_NOTIFY_CALLBACK, PBT_APMRESUMEAUTOMATIC, PBT_APMSUSPEND,
+    };
+
+    unsafe extern "system" fn notified(context: *const c_void, kind: u32, _: *const c_void) -> u32 {
+        // SAFETY: the context is the sender leaked below, alive for good
+        let events = unsafe { &*context.cast::<UnboundedSender<PowerEvent>>() };
+        match kind {
+            // Windows waits for the callback before suspending
+            PBT_APMSUSPEND => suspend_and_wait(events),
+            PBT_APMRESUMEAUTOMATIC => {
+                let _ = events.send(PowerEvent::Resumed);
+            }
+            _ => {}
+        }
+        0
+    }
+
+    // Both are leaked since the subscription lasts as long as the app
+    let params = Box::leak(Box::new(DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS {
+        Callback: Some(notified),
+        Context: Box::into_raw(Box::new(events)).cast(),
+    }));
+    let mut registration = std::ptr::null_mut();
+    // SAFETY: `params` outlives the registration, which is never undone
+    let error = unsafe {
+        PowerRegisterSuspendResumeNotification(
+            DEVICE_NOTIFY_CALLBACK,
+            std::ptr::from_mut(params).cast(),
+            &mut registration,
+        )
+    };
+    if error != 0 {
+        tracing::info!(error, "no sleep notifications from Windows");
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn subscribe(events: UnboundedSender<PowerEvent>) {
+    use std::ffi::c_void;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    type IoConnect = u32;
+    type Callback = extern "C" fn(*mut c_void, u32, u32, *mut c_void);
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IORegisterForSystemPower(
+            refcon: *mut c_void,
+            port: *mut *mut c_void,
+            callback: Callback,
+            notifier: *mut u32,
+        ) -> IoConnect;
+        fn IONotificationPortGetRunLoopSource(port: *mut c_void) -> *mut c_void;
+        fn IOAllowPowerChange(kernel_port: IoConnect, notification: isize) -> i32;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        static kCFRunLoopDefaultMode: *const c_void;
+        fn CFRunLoopGetCurrent() -> *mut c_void;
+        fn CFRunLoopAddSource(run_loop: *mut c_void, source: *mut c_void, mode: *const c_void);
+        fn CFRunLoopRun();
+    }
+
+    const CAN_SYSTEM_SLEEP: u32 = 0xe000_0270;
+    const SYSTEM_WILL_SLEEP: u32 = 0xe000_0280;
+    const SYSTEM_HAS_POWERED_ON: u32 = 0xe000_0300;
+
+    static ROOT_PORT: AtomicU32 = AtomicU32::new(0);
+
+    extern "C" fn notified(context: *mut c_void, _: u32, kind: u32, argument: *mut c_void) {
+        // SAFETY: the context is the sender leaked below, alive for good
+        let events = unsafe { &*context.cast::<UnboundedSender<PowerEvent>>() };
+        let allow = || {
+            // SAFETY: the port was registered before any notification came
+            unsafe { IOAllowPowerChange(ROOT_PORT.load(Ordering::Acquire), argument as isize) };
+        };
+        match kind {
+            CAN_SYSTEM_SLEEP => allow(),
+            // macOS waits for the go-ahead before sleeping
+            SYSTEM_WILL_SLEEP => {
+                suspend_and_wait(events);
+                allow();
+            }
+            SYSTEM_HAS_POWERED_ON => {
+                let _ = events.send(PowerEvent::Resumed);
+            }
+            _ => {}
+        }
+    }
+
+    // Notifications arrive on a run loop, which this thread spends its life
+    // running
+    std::thread::spawn(move || {
+        let context = Box::into_raw(Box::new(events)).cast();
+        let mut port = std::ptr::null_mut();
+        let mut notifier = 0;
+        // SAFETY: the context lives for good, and the port is only used
+        // once registration succeeded
+        unsafe {
+            let root = IORegisterForSystemPower(context, &mut port, notified, &mut notifier);
+            if root == 0 {
+                tracing::info!("no sleep notifications from IOKit");
+                return;
+            }
+            ROOT_PORT.store(root, Ordering::Release);
+            let source = IONotificationPortGetRunLoopSource(port);
+            CFRunLoopAddSource(CFRunLoopGetCurrent(), source, kCFRunLoopDefaultMode);
+            CFRunLoopRun();
+        }
+    });
+}
+
+/// Elsewhere the OS says nothing.
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn subscribe(_events: UnboundedSender<PowerEvent>) {}
@@ -0,0 +1,177 @@
+use std::io::ErrorKind;
+use std::process::ExitStatus;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout};
+
+use crate::error::CommandError;
+
+/// Explanation used when the handler stops reading its request early.
+pub const EARLY_EXIT: &str = "Python exited before reading the whole request";
+
+/// Reap `child`. A child that has already exited and been reaped elsewhere
+/// is not an error; there is simply no status left to report.
+pub async fn reap(child: &mut Child) -> Result<Option<ExitStatus>, String> {
+    let result = match child.try_wait() {
+        Ok(Some(status)) => return Ok(Some(status)),
+        Ok(None) => child.wait().await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(status) => Ok(Some(status)),
+        #[cfg(unix)]
+        // `waitpid` says so once someone else has reaped the child
+        Err(e) if e.raw_os_error() == Some(libc::ECHILD) => Ok(None),
+        Err(e) => Err(format!("Failed to wait for python: {}", e)),
+    }
+}
+
+/// Write the request to the child's stdin and close it. If the child exits
+/// before consuming everything, report that together with its stderr
+/// rather than a bare broken-pipe error.
+pub async fn write_request(child: &mut Child, request: &str) -> Result<(), CommandError> {
+    write_request_open(child, request).await.map(drop)
+}
+
+/// Like `write_request`, but hand back stdin so more input can follow.
+pub async fn write_request_open(
+    child: &mut Child,
+    request: &str,
+) -> Result<ChildStdin, CommandError> {
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| "Failed to open python stdin".to_string())?;
+
+    match stdin.write_all(request.as_bytes()).await {
+        Ok(()) => Ok(stdin),
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => {
+            drop(stdin);
+            let mut stderr = String::new();
+            if let Some(mut pipe) = child.stderr.take() {
+                let _ = pipe.read_to_string(&mut stderr).await;
+            }
+            let status = reap(child).await?;
+            Err(CommandError::PythonError {
+                message: EARLY_EXIT.to_string(),
+                stderr,
+                exit_code: status.and_then(|status| status.code()),
+            })
+        }
+        Err(e) => Err(format!("Failed to send request to python: {}", e).into()),
+    }
+}
+
+/// Kill `child` and reap it, returning the status it actually exited with.
+/// A child that exited before the kill landed reports its own status.
+pub async fn terminate(child: &mut Child) -> Result<Option<ExitStatus>, String> {
+    if let Err(e) = child.start_kill() {
+        // Tokio refuses to signal a child it has already reaped
+        if e.kind() != ErrorKind::InvalidInput {
+            return Err(format!("Failed to kill python: {}", e));
+        }
+    }
+    reap(child).await
+}
+
+/// A line of handler output.
+pub struct OutputLine {
+    pub text: String,
+    /// False for bytes left without a newline at EOF, e.g. a chunk the
+    /// handler died while writing.
+    pub terminated: bool,
+}
+
+/// Where the handler's stdout lines are read from.
+pub enum HandlerOutput {
+    Pipe(BufReader<ChildStdout>),
+    /// Response body of a remote backend.
+    Http(Box<dyn AsyncBufRead + Send + Unpin>),
+    #[cfg(all(unix, feature = "pty"))]
+    Pty(crate::pty::PtyOutput),
+}
+
+impl HandlerOutput {
+    pub async fn next_line(&mut self) -> std::io::Result<Option<OutputLine>> {
+        match self {
+            HandlerOutput::Pipe(reader) => read_line(reader).await,
+            HandlerOutput::Http(reader) => read_line(reader).await,
+            #[cfg(all(unix, feature = "pty"))]
+            HandlerOutput::Pty(pty) => Ok(pty.next_line().await),
+        }
+    }
+}
+
+async fn read_line(
+    reader: &mut (impl AsyncBufRead + Unpin),
+) -> std::io::Result<Option<OutputLine>> {
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line).await? == 0 {
+        return Ok(None);
+    }
+    let terminated = line.ends_with(b"\n");
+    let text = String::from_utf8_lossy(&line);
+    Ok(Some(OutputLine {
+        text: text.trim_end_matches(['\r', '\n']).to_string(),
+        terminated,
+    }))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::process::Stdio;
+    use std::time::Duration;
+    use tokio::process::Command;
+
+    /// A child that has already exited, but hasn't been waited for.
+    async fn exited(code: i32) -> Child {
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(format!("exit {}", code))
+            .stdin(Stdio::null())
+            .spawn()
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        child
+    }
+
+    #[tokio::test]
+    async fn reap_reports_the_status_of_an_exited_child() {
+        let mut child = exited(3).await;
+        let status = reap(&mut child).await.unwrap();
+        assert_eq!(status.and_then(|status| status.code()), Some(3));
+    }
+
+    #[tokio::test]
+    async fn terminate_reports_the_status_the_child_exited_with() {
+        let mut child = exited(3).await;
+        let status = terminate(&mut child).await.unwrap();
+        assert_eq!(status.and_then(|status| status.code()), Some(3));
+
+        // Already reaped; still not an error
+        let status = terminate(&mut child).await.unwrap();
+        assert_eq!(status.and_then(|status| status.code()), Some(3));
+    }
+
+    #[tokio::test]
+    async fn a_handler_exiting_before_reading_is_reported_as_such() {
+        let mut child = Command::new("python3")
+            .args(["-c", "import sys; sys.exit(2)"])
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        // Far more than a pipe holds, so the write can't complete
+        let request = "x".repeat(4 << 20);
+        match write_request(&mut child, &request).await {
+            Err(CommandError::PythonError {
+                message, exit_code, ..
+            }) => {
+                assert_eq!(message, EARLY_EXIT);
+                assert_eq!(exit_code, Some(2));
+            }
+            other => panic!("expected an early exit, got {:?}", other),
+        }
+    }
+}
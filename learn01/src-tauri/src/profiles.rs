@@ -0,0 +1,170 @@
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, State};
+use tokio::sync::Semaphore;
+
+use crate::config::{ConfigState, PythonConfig};
+use crate::diagnostics::{self, Diagnostics};
+use crate::error::CommandError;
+use crate::pools::WorkerPools;
+use crate::storage;
+
+/// Profiles checked at once by `health_check_all_profiles`.
+const MAX_CONCURRENT_CHECKS: usize = 4;
+
+/// How long one profile's check may take before it's reported as timed out.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Outcome of checking one saved profile.
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum HealthStatus {
+    Healthy {
+        diagnostics: Diagnostics,
+    },
+    Unhealthy {
+        diagnostics: Diagnostics,
+    },
+    /// The profile couldn't be read or doesn't validate.
+    Invalid {
+        error: String,
+    },
+    TimedOut,
+}
+
+/// Directory holding one `<name>.json` file per saved config.
+fn profiles_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    storage::app_data_subdir(app, "profiles")
+}
+
+/// Like session ids, but spaces are allowed since people name profiles.
+fn profile_file(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == ' ');
+    if !valid {
+        return Err(format!("Invalid profile name: {:?}", name));
+    }
+    Ok(dir.join(format!("{}.json", name)))
+}
+
+/// The saved profile `name`, validated.
+fn read_profile(dir: &Path, name: &str) -> Result<PythonConfig, String> {
+    let path = profile_file(dir, name)?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(format!("Profile not found: {}", name))
+        }
+        Err(e) => return Err(format!("Failed to read profile: {}", e)),
+    };
+    let profile: PythonConfig = serde_json::from_str(&contents)
+        .map_err(|e| format!("Corrupt profile {:?}: {}", name, e))?;
+    profile.validate()?;
+    Ok(profile)
+}
+
+/// Save the active config under `name`, replacing any profile of that name.
+#[tauri::command]
+pub fn save_python_profile(
+    app: AppHandle,
+    config: State<'_, ConfigState>,
+    name: String,
+) -> Result<(), CommandError> {
+    let dir = profiles_dir(&app)?;
+    let path = profile_file(&dir, &name)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create profile directory: {}", e))?;
+
+    let contents = serde_json::to_vec_pretty(&config.get()).map_err(|e| e.to_string())?;
+    storage::write_atomic(&path, &contents)
+        .map_err(|e| format!("Failed to save profile: {}", e))?;
+    Ok(())
+}
+
+/// Names of the saved profiles, sorted.
+#[tauri::command]
+pub fn list_python_profiles(app: AppHandle) -> Result<Vec<String>, CommandError> {
+    let entries = match fs::read_dir(profiles_dir(&app)?) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read profile directory: {}", e).into()),
+    };
+
+    let mut names: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("json"))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Make the profile `name` the active config. Worker pools restart their
+/// workers with it; streams already running keep the settings they started
+/// with.
+#[tauri::command]
+pub async fn load_python_profile(
+    app: AppHandle,
+    config: State<'_, ConfigState>,
+    pools: State<'_, WorkerPools>,
+    name: String,
+) -> Result<PythonConfig, CommandError> {
+    let profile = read_profile(&profiles_dir(&app)?, &name)?;
+
+    config.update(|config| *config = profile.clone());
+    pools.restart_workers(&profile).await;
+    tracing::info!(name, "loaded python profile");
+    Ok(profile)
+}
+
+#[tauri::command]
+pub fn delete_python_profile(app: AppHandle, name: String) -> Result<(), CommandError> {
+    let path = profile_file(&profiles_dir(&app)?, &name)?;
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err(format!("Profile not found: {}", name).into())
+        }
+        Err(e) => Err(format!("Failed to delete profile: {}", e).into()),
+    }
+}
+
+/// Self-test every saved profile, a few at a time, without touching the
+/// active config. A check that hangs is reported as timed out rather than
+/// holding up the rest.
+#[tauri::command]
+pub async fn health_check_all_profiles(
+    app: AppHandle,
+) -> Result<Vec<(String, HealthStatus)>, CommandError> {
+    let dir = profiles_dir(&app)?;
+    let slots = Semaphore::new(MAX_CONCURRENT_CHECKS);
+    let checks = list_python_profiles(app)?.into_iter().map(|name| {
+        let (dir, slots) = (&dir, &slots);
+        async move {
+            let _slot = slots.acquire().await.expect("semaphore never closed");
+            let status = match read_profile(dir, &name) {
+                Err(error) => HealthStatus::Invalid { error },
+                Ok(profile) => {
+                    match tokio::time::timeout(
+                        CHECK_TIMEOUT,
+                        diagnostics::self_test_isolated(&profile),
+                    )
+                    .await
+                    {
+                        Ok(diagnostics) if diagnostics.is_healthy() => {
+                            HealthStatus::Healthy { diagnostics }
+                        }
+                        Ok(diagnostics) => HealthStatus::Unhealthy { diagnostics },
+                        Err(_) => HealthStatus::TimedOut,
+                    }
+                }
+            };
+            (name, status)
+        }
+    });
+    Ok(futures_util::future::join_all(checks).await)
+}
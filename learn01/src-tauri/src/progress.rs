@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::State;
+
+/// How far a long-running task has got, reported on `progress` chunks.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Progress {
+    pub processed: u64,
+    /// Unknown for tasks that can't count their work up front.
+    pub total: Option<u64>,
+    pub current_file: Option<String>,
+}
+
+/// Latest progress of each session's stream, kept after the stream ends so
+/// a reloaded UI can catch up.
+#[derive(Default)]
+pub struct StreamProgress(Mutex<HashMap<String, Progress>>);
+
+impl StreamProgress {
+    pub fn update(&self, session_id: &str, progress: &Progress) {
+        self.0
+            .lock()
+            .unwrap()
+            .insert(session_id.to_string(), progress.clone());
+    }
+
+    /// Sessions with recorded progress, sorted.
+    pub fn sessions(&self) -> Vec<String> {
+        let mut sessions: Vec<String> = self.0.lock().unwrap().keys().cloned().collect();
+        sessions.sort();
+        sessions
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+/// The last progress reported by a stream of `session_id`, if any.
+#[tauri::command]
+pub fn get_stream_progress(
+    progress: State<'_, StreamProgress>,
+    session_id: String,
+) -> Option<Progress> {
+    progress.0.lock().unwrap().get(&session_id).cloned()
+}
@@ -0,0 +1,78 @@
+use portable_pty::{native_pty_system, MasterPty, PtySize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader};
+use tokio::sync::mpsc;
+
+use crate::process::OutputLine;
+
+/// Output of a handler whose stdout is a pseudo-terminal, so tools that
+/// check for a TTY (progress bars, prompts) behave as they would in a shell.
+pub struct PtyOutput {
+    // The terminal hangs up once the master side is closed
+    _master: Box<dyn MasterPty + Send>,
+    lines: mpsc::Receiver<OutputLine>,
+}
+
+impl PtyOutput {
+    /// Next line of output, or `None` once the handler has closed the
+    /// terminal.
+    pub async fn next_line(&mut self) -> Option<OutputLine> {
+        self.lines.recv().await
+    }
+}
+
+/// Open a pseudo-terminal. Returns its output reader and the terminal
+/// device to hand to the child as stdout; drop the caller's copy of the
+/// device after spawning or the output never ends.
+pub fn open() -> Result<(PtyOutput, File), String> {
+    let pair = native_pty_system()
+        .openpty(PtySize::default())
+        .map_err(|e| format!("Failed to open a pseudo-terminal: {}", e))?;
+    let device = pair
+        .master
+        .tty_name()
+        .ok_or_else(|| "Pseudo-terminal has no device name".to_string())?;
+    let slave = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&device)
+        .map_err(|e| format!("Failed to open {:?}: {}", device, e))?;
+    drop(pair.slave);
+
+    let reader = pair
+        .master
+        .try_clone_reader()
+        .map_err(|e| format!("Failed to read the pseudo-terminal: {}", e))?;
+    let (sender, lines) = mpsc::channel(64);
+
+    // The master only offers blocking reads
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            // Reads fail with EIO once every copy of the device is closed
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            // The terminal turns `\n` into `\r\n`
+            let text = String::from_utf8_lossy(&line);
+            let output = OutputLine {
+                text: text.trim_end_matches(['\r', '\n']).to_string(),
+                terminated: line.ends_with(b"\n"),
+            };
+            if sender.blocking_send(output).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok((
+        PtyOutput {
+            _master: pair.master,
+            lines,
+        },
+        slave,
+    ))
+}
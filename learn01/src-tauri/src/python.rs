@@ -0,0 +1,274 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, State};
+use tokio::process::{Child, Command as AsyncCommand};
+
+use crate::config::PythonConfig;
+use crate::detect::DetectionCache;
+
+/// Name of the Python executable for the current platform.
+pub fn python_command() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "python"
+    } else {
+        "python3"
+    }
+}
+
+/// Interpreter to launch: the configured override, else the detected
+/// interpreter, else the platform default on PATH.
+pub fn interpreter(app: &AppHandle, config: &PythonConfig) -> PathBuf {
+    config
+        .interpreter
+        .clone()
+        .or_else(|| {
+            app.state::<DetectionCache>()
+                .get()
+                .map(|detected| detected.path)
+        })
+        .unwrap_or_else(|| PathBuf::from(python_command()))
+}
+
+/// Command running the configured interpreter with the configured
+/// environment and sandbox; the caller adds the script and its arguments.
+pub fn command(app: &AppHandle, config: &PythonConfig) -> Result<Command, String> {
+    let mut command = Command::new(interpreter(app, config));
+    if let Some(sandbox) = &config.sandbox {
+        sandbox.apply(&mut command);
+    }
+    if let Some(python_path) = python_path(config)? {
+        command.env("PYTHONPATH", python_path);
+    }
+    Ok(command)
+}
+
+/// Inherited variables worth showing in `last_python_command`, since they
+/// change which Python runs and what it imports.
+const RELEVANT_ENV: [&str; 5] = [
+    "PATH",
+    "PYTHONPATH",
+    "PYTHONHOME",
+    "VIRTUAL_ENV",
+    "CONDA_PREFIX",
+];
+
+/// Variables whose names contain any of these have their values hidden.
+const SECRET_MARKERS: [&str; 5] = ["KEY", "TOKEN", "SECRET", "PASSWORD", "CREDENTIAL"];
+
+/// A handler launch, with enough detail to repeat it in a terminal.
+#[derive(Clone, Serialize)]
+pub struct LaunchedCommand {
+    interpreter: String,
+    args: Vec<String>,
+    working_dir: Option<PathBuf>,
+    /// Variables set for the handler and relevant inherited ones, with
+    /// secrets redacted.
+    env: BTreeMap<String, String>,
+    /// The whole thing as a POSIX shell command line.
+    command_line: String,
+}
+
+/// The most recent handler launch.
+#[derive(Default)]
+pub struct LastCommand(Mutex<Option<LaunchedCommand>>);
+
+impl LastCommand {
+    pub fn get(&self) -> Option<LaunchedCommand> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        *self.0.lock().unwrap() = None;
+    }
+}
+
+/// Spawn a handler built from `config`, remembering how for
+/// `last_python_command`.
+pub fn spawn(
+    app: &AppHandle,
+    config: &PythonConfig,
+    command: &mut AsyncCommand,
+) -> std::io::Result<Child> {
+    let launched = describe(config, command.as_std());
+    *app.state::<LastCommand>().0.lock().unwrap() = Some(launched);
+    command.spawn()
+}
+
+fn describe(config: &PythonConfig, command: &Command) -> LaunchedCommand {
+    let text = |value: &OsStr| value.to_string_lossy().into_owned();
+    let mut env: BTreeMap<String, String> = RELEVANT_ENV
+        .iter()
+        .filter_map(|key| Some((key.to_string(), text(&inherited(config, key)?))))
+        .collect();
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => env.insert(text(key), text(value)),
+            None => env.remove(&text(key)),
+        };
+    }
+    for (key, value) in env.iter_mut() {
+        let upper = key.to_uppercase();
+        if SECRET_MARKERS.iter().any(|marker| upper.contains(marker)) {
+            *value = "<redacted>".to_string();
+        }
+    }
+
+    let interpreter = text(command.get_program());
+    let args: Vec<String> = command.get_args().map(text).collect();
+    let working_dir = command
+        .get_current_dir()
+        .map(PathBuf::from)
+        .or_else(|| std::env::current_dir().ok());
+
+    let mut words = Vec::new();
+    if let Some(dir) = &working_dir {
+        words.push(format!("cd {} &&", shell_quote(&dir.to_string_lossy())));
+    }
+    words.extend(
+        env.iter()
+            .map(|(key, value)| format!("{}={}", key, shell_quote(value))),
+    );
+    words.extend(
+        std::iter::once(&interpreter)
+            .chain(&args)
+            .map(|word| shell_quote(word)),
+    );
+
+    LaunchedCommand {
+        interpreter,
+        args,
+        working_dir,
+        env,
+        command_line: words.join(" "),
+    }
+}
+
+fn shell_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+/// How the handler was most recently launched, to reproduce problems
+/// outside the app. The request itself goes to stdin and isn't included.
+#[tauri::command]
+pub fn last_python_command(last: State<'_, LastCommand>) -> Option<LaunchedCommand> {
+    last.get()
+}
+
+/// The app's value of `key`, if handlers inherit it past the sandbox.
+fn inherited(config: &PythonConfig, key: &str) -> Option<OsString> {
+    match &config.sandbox {
+        Some(sandbox) if !sandbox.inherits(key) => None,
+        _ => std::env::var_os(key),
+    }
+}
+
+/// Configured search paths followed by the inherited `PYTHONPATH`, if any.
+fn python_path(config: &PythonConfig) -> Result<Option<OsString>, String> {
+    if config.python_path.is_empty() {
+        return Ok(None);
+    }
+
+    let mut paths = config.python_path.clone();
+    if let Some(inherited) = inherited(config, "PYTHONPATH") {
+        paths.extend(std::env::split_paths(&inherited));
+    }
+    std::env::join_paths(paths)
+        .map(Some)
+        .map_err(|e| format!("Invalid PYTHONPATH entry: {}", e))
+}
+
+/// Command running the chat handler for one request, with piped stdio that
+/// callers may override before spawning.
+pub fn handler_command(
+    app: &AppHandle,
+    config: &PythonConfig,
+    trace_id: &str,
+) -> Result<AsyncCommand, String> {
+    let script = handler_script()?;
+    if !script.exists() {
+        return Err(format!("Python script not found at: {:?}", script));
+    }
+
+    let mut command = AsyncCommand::from(self::command(app, config)?);
+    command
+        .arg(script)
+        .env("TRACE_ID", trace_id)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    Ok(command)
+}
+
+/// Location of the chat handler script.
+pub fn handler_script() -> Result<PathBuf, String> {
+    if cfg!(debug_assertions) {
+        // Development: get absolute path
+        let mut path = std::env::current_dir()
+            .map_err(|e| format!("Failed to get current directory: {}", e))?;
+        path.push("python");
+        path.push("chat_handler.py");
+        Ok(path)
+    } else {
+        // Production: bundle with the app
+        Ok(PathBuf::from("python/chat_handler.py"))
+    }
+}
+
+/// Find the interpreter belonging to the conda environment `name`.
+pub async fn conda_interpreter(name: &str) -> Result<PathBuf, String> {
+    // Already running inside the requested environment
+    if std::env::var("CONDA_DEFAULT_ENV").as_deref() == Ok(name) {
+        if let Some(prefix) = std::env::var_os("CONDA_PREFIX") {
+            let mut path = PathBuf::from(prefix);
+            if cfg!(target_os = "windows") {
+                path.push("python.exe");
+            } else {
+                path.push("bin");
+                path.push("python");
+            }
+            if path.exists() {
+                return Ok(path);
+            }
+        }
+    }
+
+    let conda = std::env::var_os("CONDA_EXE").unwrap_or_else(|| "conda".into());
+    let output = AsyncCommand::new(conda)
+        .args(["run", "-n", name, "python", "-c"])
+        .arg("import sys; print(sys.executable)")
+        .output()
+        .await
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => "conda is not installed or not on PATH".to_string(),
+            _ => format!("Failed to execute conda: {}", e),
+        })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "Conda environment '{}' could not be used: {}",
+            name,
+            stderr.trim()
+        ));
+    }
+
+    // `conda run` may print activation noise first; the path is the last line
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let path = stdout
+        .lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .ok_or_else(|| format!("Conda environment '{}' did not report an interpreter", name))?;
+
+    if !path.exists() {
+        return Err(format!("Conda interpreter not found at: {:?}", path));
+    }
+    Ok(path)
+}
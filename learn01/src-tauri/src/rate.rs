@@ -0,0 +1,87 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::StreamChunk;
+
+/// Token bucket capping how many chunks a stream emits per second. Chunks
+/// over the cap wait their turn, with consecutive content merged into one,
+/// so nothing is dropped. Terminal and error chunks are never held back.
+pub struct EmitRate {
+    per_sec: f64,
+    /// Emits available right now, up to one second's worth.
+    tokens: f64,
+    refilled: Instant,
+    queued: VecDeque<StreamChunk>,
+}
+
+impl EmitRate {
+    pub fn new(max_per_sec: u32) -> Self {
+        Self {
+            per_sec: f64::from(max_per_sec),
+            tokens: f64::from(max_per_sec),
+            refilled: Instant::now(),
+            queued: VecDeque::new(),
+        }
+    }
+
+    /// Feed chunks ready to emit. Returns those the cap lets through now,
+    /// in order.
+    pub fn push(&mut self, chunks: Vec<StreamChunk>) -> Vec<StreamChunk> {
+        let mut ready = Vec::new();
+        for chunk in chunks {
+            if chunk.is_terminal() || chunk.chunk_type == "error" {
+                ready.extend(self.queued.drain(..));
+                ready.push(chunk);
+                continue;
+            }
+            match self.queued.back_mut() {
+                Some(last) if last.is_content() && chunk.is_content() => {
+                    let content = chunk.content.unwrap_or_default();
+                    last.content
+                        .get_or_insert_with(String::new)
+                        .push_str(&content);
+                }
+                _ => self.queued.push_back(chunk),
+            }
+            self.release(&mut ready);
+        }
+        ready
+    }
+
+    /// When the next held chunk may go out, if any are held.
+    pub fn deadline(&self) -> Option<Instant> {
+        if self.queued.is_empty() {
+            return None;
+        }
+        let wait = (1.0 - self.tokens).max(0.0) / self.per_sec;
+        Some(self.refilled + Duration::from_secs_f64(wait))
+    }
+
+    /// Emit whatever the cap allows by now.
+    pub fn flush(&mut self) -> Vec<StreamChunk> {
+        let mut ready = Vec::new();
+        self.release(&mut ready);
+        ready
+    }
+
+    /// Everything still held, at end of stream.
+    pub fn finish(&mut self) -> Vec<StreamChunk> {
+        self.queued.drain(..).collect()
+    }
+
+    fn release(&mut self, ready: &mut Vec<StreamChunk>) {
+        let now = Instant::now();
+        let refill = now.duration_since(self.refilled).as_secs_f64() * self.per_sec;
+        self.tokens = (self.tokens + refill).min(self.per_sec);
+        self.refilled = now;
+
+        while self.tokens >= 1.0 {
+            let Some(chunk) = self.queued.pop_front() else {
+                break;
+            };
+            self.tokens -= 1.0;
+            ready.push(chunk);
+        }
+    }
+}
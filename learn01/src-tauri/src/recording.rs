@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::State;
+use tokio::time::Instant;
+
+use crate::error::CommandError;
+use crate::pipeline::StreamPipeline;
+use crate::sink::ChunkSink;
+use crate::streams::StreamRegistry;
+use crate::{StreamChunk, StreamOptions};
+
+/// One raw handler line, as stored in a `.ndjson` stream log.
+#[derive(Serialize, Deserialize)]
+struct RecordedLine {
+    /// Milliseconds since the stream started.
+    elapsed_ms: u64,
+    line: String,
+}
+
+/// Writes every raw line of a stream to a log file as it is read.
+pub struct Recorder {
+    file: File,
+    started: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create stream log {:?}: {}", path, e))?;
+        Ok(Self {
+            file,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, line: &str) -> Result<(), String> {
+        let record = RecordedLine {
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            line: line.to_string(),
+        };
+        let mut json = serde_json::to_string(&record).map_err(|e| e.to_string())?;
+        json.push('\n');
+        self.file
+            .write_all(json.as_bytes())
+            .map_err(|e| format!("Failed to write stream log: {}", e))
+    }
+}
+
+/// Re-emit a recorded stream as `stream-chunk` events, with its original
+/// timing unless `realtime` is false.
+#[tauri::command]
+pub async fn replay_python_stream(
+    window: tauri::Window,
+    registry: State<'_, StreamRegistry>,
+    log_path: PathBuf,
+    realtime: Option<bool>,
+) -> Result<(), CommandError> {
+    let contents = tokio::fs::read_to_string(&log_path)
+        .await
+        .map_err(|e| format!("Failed to read stream log {:?}: {}", log_path, e))?;
+    let realtime = realtime.unwrap_or(true);
+
+    let sink = ChunkSink::Window(Box::new(window));
+    let stream = registry.register(None, None)?;
+    let mut pipeline = StreamPipeline::new(&StreamOptions::default(), None, None);
+    let started = Instant::now();
+
+    for (index, entry) in contents.lines().enumerate() {
+        if entry.trim().is_empty() {
+            continue;
+        }
+        let record: RecordedLine = serde_json::from_str(entry)
+            .map_err(|e| format!("Invalid stream log entry on line {}: {}", index + 1, e))?;
+
+        if realtime {
+            let due = started + Duration::from_millis(record.elapsed_ms);
+            tokio::select! {
+                _ = tokio::time::sleep_until(due) => {}
+                _ = stream.cancelled() => return Ok(()),
+            }
+        }
+
+        if let Ok(chunk) = serde_json::from_str::<StreamChunk>(&record.line) {
+            for chunk in pipeline.push(chunk) {
+                sink.send(&chunk)?;
+            }
+        }
+    }
+
+    for chunk in pipeline.finish() {
+        sink.send(&chunk)?;
+    }
+    Ok(())
+}
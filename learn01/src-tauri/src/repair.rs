@@ -0,0 +1,143 @@
+use crate::StreamChunk;
+
+/// Parse a handler line that failed strict parsing, after fixing the
+/// mistakes naive JSON building tends to make: trailing commas and
+/// unquoted keys. Anything still unparsable becomes a `parse_error` chunk
+/// carrying the raw line.
+pub fn parse_lenient(line: &str, error: serde_json::Error) -> StreamChunk {
+    if let Some(chunk) = repair(line).and_then(|fixed| serde_json::from_str(&fixed).ok()) {
+        tracing::warn!(line, "repaired malformed handler output");
+        return chunk;
+    }
+
+    let mut chunk = StreamChunk::new("parse_error");
+    chunk.success = Some(false);
+    chunk.error = Some(error.to_string());
+    chunk.content = Some(line.to_string());
+    chunk
+}
+
+/// The line with trailing commas dropped and bare keys quoted, or `None`
+/// if there was nothing to fix.
+fn repair(line: &str) -> Option<String> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut fixed = String::with_capacity(line.len() + 8);
+    let mut changed = false;
+    let mut in_string = false;
+    let mut escaped = false;
+    // Just after `{` or `,`, where an object key may start
+    let mut key_position = false;
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if in_string {
+            fixed.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            i += 1;
+            continue;
+        }
+
+        match c {
+            '"' => {
+                in_string = true;
+                key_position = false;
+                fixed.push(c);
+            }
+            ',' => {
+                let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+                if matches!(next, Some('}') | Some(']')) {
+                    changed = true;
+                } else {
+                    fixed.push(c);
+                    key_position = true;
+                }
+            }
+            '{' => {
+                fixed.push(c);
+                key_position = true;
+            }
+            _ if c.is_whitespace() => fixed.push(c),
+            _ if key_position && (c.is_alphabetic() || c == '_' || c == '$') => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|c| !(c.is_alphanumeric() || *c == '_' || *c == '$'))
+                    .map_or(chars.len(), |len| i + len);
+                let next = chars[end..].iter().find(|c| !c.is_whitespace());
+                let word: String = chars[i..end].iter().collect();
+                if next == Some(&':') {
+                    fixed.push('"');
+                    fixed.push_str(&word);
+                    fixed.push('"');
+                    changed = true;
+                } else {
+                    fixed.push_str(&word);
+                }
+                key_position = false;
+                i = end;
+                continue;
+            }
+            _ => {
+                fixed.push(c);
+                key_position = false;
+            }
+        }
+        i += 1;
+    }
+
+    changed.then_some(fixed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lenient(line: &str) -> StreamChunk {
+        let error = serde_json::from_str::<StreamChunk>(line).err().unwrap();
+        parse_lenient(line, error)
+    }
+
+    #[test]
+    fn drops_trailing_commas() {
+        let chunk = lenient(r#"{"type":"chunk","content":"hi",}"#);
+        assert_eq!(chunk.chunk_type, "chunk");
+        assert_eq!(chunk.content.as_deref(), Some("hi"));
+
+        let chunk =
+            lenient(r#"{"type":"tool_call","tool_call":{"id":"1","name":"f","arguments":[1,2,]}}"#);
+        let arguments = &chunk.tool_call.unwrap().arguments;
+        assert_eq!(*arguments, serde_json::json!([1, 2]));
+    }
+
+    #[test]
+    fn quotes_bare_keys() {
+        let chunk = lenient(r#"{type:"chunk", content:"hi"}"#);
+        assert_eq!(chunk.chunk_type, "chunk");
+        assert_eq!(chunk.content.as_deref(), Some("hi"));
+    }
+
+    #[test]
+    fn leaves_literals_after_a_comma_alone() {
+        let chunk =
+            lenient(r#"{type:"complete", success:true, error:null, finish_reason:"stop",}"#);
+        assert_eq!(chunk.chunk_type, "complete");
+        assert_eq!(chunk.success, Some(true));
+        assert_eq!(chunk.error, None);
+        assert_eq!(chunk.finish_reason.as_deref(), Some("stop"));
+    }
+
+    #[test]
+    fn unrepairable_lines_become_parse_errors() {
+        let line = r#"{"type":"chunk","content":"unterminated}"#;
+        let chunk = lenient(line);
+        assert_eq!(chunk.chunk_type, "parse_error");
+        assert_eq!(chunk.success, Some(false));
+        assert_eq!(chunk.content.as_deref(), Some(line));
+        assert!(chunk.error.is_some());
+    }
+}
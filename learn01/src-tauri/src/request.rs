@@ -0,0 +1,59 @@
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::history::HistoryMessage;
+
+/// Request written to the handler's stdin as a single JSON line.
+#[derive(Serialize)]
+pub struct PythonRequest {
+    #[serde(rename = "type")]
+    pub request_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub trace_id: String,
+    /// Earlier messages of the conversation, oldest first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<HistoryMessage>,
+    /// Sampling seed for reproducible output, if the upstream supports it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl PythonRequest {
+    pub fn chat(message: &str, trace_id: &str) -> Self {
+        Self {
+            request_type: "chat",
+            message: Some(message.to_string()),
+            trace_id: trace_id.to_string(),
+            history: Vec::new(),
+            seed: None,
+        }
+    }
+
+    /// Ask the handler to ping its upstream API instead of chatting.
+    pub fn connectivity_check(trace_id: &str) -> Self {
+        Self {
+            request_type: "connectivity_check",
+            message: None,
+            trace_id: trace_id.to_string(),
+            history: Vec::new(),
+            seed: None,
+        }
+    }
+
+    /// The request as a newline-terminated JSON line.
+    pub fn to_line(&self) -> Result<String, String> {
+        let mut line = serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize request: {}", e))?;
+        line.push('\n');
+        Ok(line)
+    }
+}
+
+/// Use the caller's trace id, or mint a fresh one.
+pub fn trace_id(requested: Option<&str>) -> String {
+    match requested {
+        Some(trace_id) if !trace_id.is_empty() => trace_id.to_string(),
+        _ => Uuid::new_v4().simple().to_string(),
+    }
+}
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+/// Restrictions applied to every handler process, for deployments running
+/// handlers they don't fully trust.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sandbox {
+    /// File mode creation mask for the handler, e.g. 63 (`0o077`) so the
+    /// files it creates are private. Unix only.
+    pub umask: u32,
+    /// Group to run the handler as; supplementary groups are dropped.
+    /// Needs the app to be privileged. Unix only.
+    pub gid: Option<u32>,
+    /// User to run the handler as. Needs the app to be privileged. Unix only.
+    pub uid: Option<u32>,
+    /// Environment variables the handler inherits; the rest are cleared.
+    pub env_allowlist: Vec<String>,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self {
+            umask: 0o077,
+            gid: None,
+            uid: None,
+            env_allowlist: ["PATH", "HOME", "LANG", "LC_ALL", "TMPDIR", "SYSTEMROOT"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+impl Sandbox {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.umask > 0o777 {
+            return Err(format!("Invalid umask: {:o}", self.umask));
+        }
+        if cfg!(not(unix)) && (self.gid.is_some() || self.uid.is_some()) {
+            return Err("Switching user or group is only supported on Unix".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether the handler inherits the variable `key`.
+    pub fn inherits(&self, key: &str) -> bool {
+        self.env_allowlist.iter().any(|allowed| allowed == key)
+    }
+
+    /// Apply the restrictions to a handler command before anything else is
+    /// added to its environment.
+    pub fn apply(&self, command: &mut Command) {
+        command.env_clear();
+        for key in &self.env_allowlist {
+            if let Some(value) = std::env::var_os(key) {
+                command.env(key, value);
+            }
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+
+            let Self {
+                umask, gid, uid, ..
+            } = *self;
+            // SAFETY: the hook runs in the forked child before exec and only
+            // makes async-signal-safe calls, without allocating
+            unsafe {
+                command.pre_exec(move || {
+                    libc::umask(umask as libc::mode_t);
+                    if (gid.is_some() || uid.is_some()) && libc::setgroups(0, std::ptr::null()) != 0
+                    {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    // The group goes first; it can't be changed once the user has
+                    if gid.is_some_and(|gid| libc::setgid(gid) != 0) {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    if uid.is_some_and(|uid| libc::setuid(uid) != 0) {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+    }
+}
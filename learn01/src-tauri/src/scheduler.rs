@@ -0,0 +1,76 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tokio::time::Instant;
+
+/// Longest a stream may keep reading before the next one gets a turn.
+const SLICE: Duration = Duration::from_millis(20);
+
+/// Most lines a stream reads in one turn.
+const LINES_PER_TURN: usize = 32;
+
+/// Takes turns reading handler output once more streams run than
+/// `fair_read_above` allows: a stream waits its turn before reading, then
+/// reads for at most one slice or `LINES_PER_TURN` lines. Turns are handed
+/// out first come first served, so every busy stream makes progress rather
+/// than one heavy stream flooding the webview.
+#[derive(Default)]
+pub struct ReadScheduler(Arc<Mutex<()>>);
+
+/// A stream's place in line, resolving to its turn. Dropping it gives the
+/// place up.
+pub type Queued = Pin<Box<dyn Future<Output = Turn> + Send>>;
+
+/// A stream's right to read; the next stream in line goes once it's dropped.
+pub struct Turn {
+    _guard: OwnedMutexGuard<()>,
+    ends: Instant,
+    lines: usize,
+}
+
+impl ReadScheduler {
+    /// Get in line behind the streams already waiting for a turn. The slice
+    /// starts once the turn comes.
+    pub fn queue(&self) -> Queued {
+        let lock = self.0.clone();
+        Box::pin(async move {
+            let guard = lock.lock_owned().await;
+            Turn {
+                _guard: guard,
+                ends: Instant::now() + SLICE,
+                lines: 0,
+            }
+        })
+    }
+}
+
+impl Turn {
+    /// When the turn has to be given up even if the handler is quiet.
+    pub fn ends(&self) -> Instant {
+        self.ends
+    }
+
+    /// Count a line read in this turn. Returns whether the turn is used up.
+    pub fn take_line(&mut self) -> bool {
+        self.lines += 1;
+        self.lines >= LINES_PER_TURN || Instant::now() >= self.ends
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn a_queued_stream_waits_for_the_turn_before_it() {
+        let scheduler = ReadScheduler::default();
+        let first = scheduler.queue().await;
+        let mut second = scheduler.queue();
+        let wait = Duration::from_millis(50);
+        assert!(tokio::time::timeout(wait, &mut second).await.is_err());
+        drop(first);
+        assert!(tokio::time::timeout(wait, second).await.is_ok());
+    }
+}
@@ -0,0 +1,33 @@
+use tauri::ipc::Channel;
+use tauri::{Emitter, Window};
+
+use std::sync::Arc;
+
+use crate::detached::DetachedStream;
+use crate::StreamChunk;
+
+/// Where a stream's chunks are delivered.
+#[derive(Clone)]
+pub enum ChunkSink {
+    /// Broadcast as `stream-chunk` events; any listener in any window sees them.
+    Window(Box<Window>),
+    /// Sent only to the invoke that started the stream.
+    Channel(Channel<StreamChunk>),
+    /// Buffered for whichever frontend attaches; never fails.
+    Detached(Arc<DetachedStream>),
+}
+
+impl ChunkSink {
+    pub fn send(&self, chunk: &StreamChunk) -> Result<(), String> {
+        match self {
+            ChunkSink::Window(window) => window
+                .emit("stream-chunk", chunk)
+                .map_err(|e| e.to_string()),
+            ChunkSink::Channel(channel) => channel.send(chunk.clone()).map_err(|e| e.to_string()),
+            ChunkSink::Detached(stream) => {
+                stream.send(chunk);
+                Ok(())
+            }
+        }
+    }
+}
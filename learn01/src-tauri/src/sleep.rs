@@ -0,0 +1,149 @@
+use serde::Serialize;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::mpsc;
+
+use crate::power::{self, PowerEvent};
+use crate::streams::StreamRegistry;
+
+/// How often the clocks are compared.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Time suspended between two checks that counts as a sleep rather than
+/// clock granularity.
+const SLEEP_THRESHOLD: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Serialize)]
+struct Suspending {
+    streams: usize,
+}
+
+#[derive(Clone, Serialize)]
+struct Resumed {
+    slept_secs: u64,
+    streams: usize,
+}
+
+/// Time since boot, first not counting and then counting time suspended.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn clocks() -> (Duration, Duration) {
+    (clock(libc::CLOCK_MONOTONIC), clock(libc::CLOCK_BOOTTIME))
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn clocks() -> (Duration, Duration) {
+    (clock(libc::CLOCK_UPTIME_RAW), clock(libc::CLOCK_MONOTONIC))
+}
+
+#[cfg(windows)]
+fn clocks() -> (Duration, Duration) {
+    use windows_sys::Win32::System::SystemInformation::GetTickCount64;
+    use windows_sys::Win32::System::WindowsProgramming::QueryUnbiasedInterruptTime;
+
+    let mut unbiased = 0;
+    // SAFETY: both only read the system clocks; the pointer is to a local
+    let total = unsafe {
+        QueryUnbiasedInterruptTime(&mut unbiased);
+        GetTickCount64()
+    };
+    // The unbiased interrupt time counts in units of 100ns
+    (
+        Duration::from_nanos(unbiased.saturating_mul(100)),
+        Duration::from_millis(total),
+    )
+}
+
+/// Elsewhere the clocks never drift apart, so sleep goes unnoticed.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    windows
+)))]
+fn clocks() -> (Duration, Duration) {
+    (Duration::ZERO, Duration::ZERO)
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios"
+))]
+fn clock(id: libc::clockid_t) -> Duration {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `time` is a valid timespec; this only fails for clocks the
+    // platform lacks, and these are all available there
+    unsafe { libc::clock_gettime(id, &mut time) };
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+/// How long streams get to emit what they have before the machine sleeps.
+const SUSPEND_GRACE: Duration = Duration::from_millis(500);
+
+/// Background task reacting when the machine goes to sleep and wakes up.
+///
+/// Where the OS says it is about to sleep (logind on Linux, IOKit on macOS,
+/// power notifications on Windows), running streams are told to emit what
+/// they have generated so far and a `suspended` chunk, and are cancelled
+/// there and then if `cancel_streams_after_sleep` is set. The suspend is
+/// held off for `SUSPEND_GRACE` meanwhile, and `system-suspending` is
+/// emitted.
+///
+/// Waking up is noticed from the OS as well, or else at the next check of
+/// a clock that stops while suspended against one that keeps counting.
+/// Unlike the wall clock, neither jumps when the time is set. Running
+/// streams are then asked to check whether their handler survived, and
+/// `system-resumed` is emitted with the time spent asleep.
+pub async fn watch_for_sleep(app: AppHandle) {
+    let (sender, mut events) = mpsc::unbounded_channel();
+    power::subscribe(sender);
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    let (mut last_awake, mut last_total) = clocks();
+    // Whether the OS said it was going to sleep, and hasn't woken up since
+    let mut asleep = false;
+    loop {
+        let woke = tokio::select! {
+            event = events.recv(), if !events.is_closed() => match event {
+                Some(PowerEvent::Suspending { delay }) => {
+                    let streams = app.state::<StreamRegistry>().suspend_all();
+                    tracing::info!(streams, "system suspending");
+                    if let Err(e) = app.emit("system-suspending", Suspending { streams }) {
+                        tracing::warn!("failed to emit system-suspending: {}", e);
+                    }
+                    tokio::time::sleep(SUSPEND_GRACE).await;
+                    drop(delay);
+                    asleep = true;
+                    continue;
+                }
+                Some(PowerEvent::Resumed) => asleep,
+                None => continue,
+            },
+            _ = interval.tick() => false,
+        };
+        let (awake, total) = clocks();
+        let slept = total
+            .saturating_sub(last_total)
+            .saturating_sub(awake.saturating_sub(last_awake));
+        // Only what the clocks saw since, so each wake up is handled once
+        (last_awake, last_total) = (awake, total);
+        if !woke && slept < SLEEP_THRESHOLD {
+            continue;
+        }
+        asleep = false;
+
+        let streams = app.state::<StreamRegistry>().wake_all();
+        tracing::info!(slept_secs = slept.as_secs(), streams, "system resumed");
+        let payload = Resumed {
+            slept_secs: slept.as_secs(),
+            streams,
+        };
+        if let Err(e) = app.emit("system-resumed", payload) {
+            tracing::warn!("failed to emit system-resumed: {}", e);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tauri::{AppHandle, Manager};
+
+use crate::backend::Backend;
+use crate::config::{ConfigState, PythonConfig};
+use crate::detached::DetachedStreams;
+use crate::detect::{DetectedPython, DetectionCache};
+use crate::error::CommandError;
+use crate::groups::StreamGroups;
+use crate::pools::WorkerPools;
+use crate::progress::StreamProgress;
+use crate::python::LastCommand;
+use crate::streams::StreamRegistry;
+use crate::tokens::TokenCounter;
+
+/// Summary of everything the app keeps in memory, for debugging.
+#[derive(Serialize)]
+pub struct StateDump {
+    /// The active config, with credentials in the backend URL hidden.
+    config: PythonConfig,
+    /// Ids of the running streams.
+    streams: Vec<String>,
+    stream_groups: Vec<String>,
+    /// Idle workers of each worker pool.
+    worker_pools: BTreeMap<String, usize>,
+    cached_encoders: usize,
+    cached_token_counts: usize,
+    /// Sessions with stored stream progress.
+    progress_sessions: Vec<String>,
+    detached_sessions: Vec<String>,
+    detected_python: Option<DetectedPython>,
+    has_last_command: bool,
+}
+
+/// These commands expose or throw away internals; release builds refuse.
+fn debug_only() -> Result<(), String> {
+    match cfg!(debug_assertions) {
+        true => Ok(()),
+        false => Err("Only available in debug builds".to_string()),
+    }
+}
+
+fn redacted(mut config: PythonConfig) -> PythonConfig {
+    if let Backend::Http { url } = &mut config.backend {
+        if let Ok(mut parsed) = reqwest::Url::parse(url) {
+            if parsed.password().is_some() {
+                let _ = parsed.set_password(Some("<redacted>"));
+            }
+            if parsed.query().is_some() {
+                parsed.set_query(Some("<redacted>"));
+            }
+            *url = parsed.to_string();
+        }
+    }
+    config
+}
+
+fn dump(app: &AppHandle) -> StateDump {
+    let (cached_encoders, cached_token_counts) = app.state::<TokenCounter>().cache_sizes();
+    StateDump {
+        config: redacted(app.state::<ConfigState>().get()),
+        streams: app.state::<StreamRegistry>().ids(),
+        stream_groups: app.state::<StreamGroups>().ids(),
+        worker_pools: app.state::<WorkerPools>().idle_workers(),
+        cached_encoders,
+        cached_token_counts,
+        progress_sessions: app.state::<StreamProgress>().sessions(),
+        detached_sessions: app.state::<DetachedStreams>().sessions(),
+        detected_python: app.state::<DetectionCache>().get(),
+        has_last_command: app.state::<LastCommand>().get().is_some(),
+    }
+}
+
+/// Snapshot of the app's managed state and caches. Debug builds only.
+#[tauri::command]
+pub fn dump_state(app: AppHandle) -> Result<StateDump, CommandError> {
+    debug_only()?;
+    Ok(dump(&app))
+}
+
+/// Cancel running streams, shut down worker pools and clear every cache and
+/// registry, leaving the config alone. Returns the state afterwards;
+/// cancelled streams disappear from it as they wind down. Debug builds only.
+#[tauri::command]
+pub async fn reset_state(app: AppHandle) -> Result<StateDump, CommandError> {
+    debug_only()?;
+    let cancelled = app.state::<StreamRegistry>().cancel_all();
+    let workers = app.state::<WorkerPools>().shutdown_all().await;
+    app.state::<StreamGroups>().clear();
+    app.state::<TokenCounter>().clear();
+    app.state::<StreamProgress>().clear();
+    app.state::<DetachedStreams>().clear();
+    app.state::<DetectionCache>().clear();
+    app.state::<LastCommand>().clear();
+    tracing::info!(cancelled, workers, "reset app state");
+    Ok(dump(&app))
+}
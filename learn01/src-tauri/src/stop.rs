@@ -0,0 +1,69 @@
+/// Watches streamed text for stop sequences, including ones split across
+/// chunk boundaries.
+pub struct StopMatcher {
+    stops: Vec<String>,
+    /// Tail of the text seen so far that could still be the start of a stop
+    /// sequence, withheld until it's known not to be.
+    held: String,
+}
+
+impl StopMatcher {
+    /// Returns `None` when there are no non-empty stop sequences.
+    pub fn new(stops: &[String]) -> Option<Self> {
+        let stops: Vec<String> = stops.iter().filter(|s| !s.is_empty()).cloned().collect();
+        if stops.is_empty() {
+            return None;
+        }
+        Some(Self {
+            stops,
+            held: String::new(),
+        })
+    }
+
+    /// Bytes currently withheld.
+    pub fn held_len(&self) -> usize {
+        self.held.len()
+    }
+
+    /// Feed the next piece of content. Returns the text that is safe to emit
+    /// and whether a stop sequence was hit; on a hit the text is truncated
+    /// right before the match.
+    pub fn push(&mut self, text: &str) -> (String, bool) {
+        let mut buffer = std::mem::take(&mut self.held);
+        buffer.push_str(text);
+
+        let earliest = self
+            .stops
+            .iter()
+            .filter_map(|stop| buffer.find(stop.as_str()))
+            .min();
+        if let Some(index) = earliest {
+            buffer.truncate(index);
+            return (buffer, true);
+        }
+
+        let hold = self.partial_match_len(&buffer);
+        self.held = buffer.split_off(buffer.len() - hold);
+        (buffer, false)
+    }
+
+    /// Release any withheld text once no more content is coming.
+    pub fn finish(&mut self) -> String {
+        std::mem::take(&mut self.held)
+    }
+
+    /// Length of the longest suffix of `buffer` that is a proper prefix of
+    /// some stop sequence.
+    fn partial_match_len(&self, buffer: &str) -> usize {
+        let longest = self.stops.iter().map(String::len).max().unwrap_or(0);
+        let max = longest.saturating_sub(1).min(buffer.len());
+        (1..=max)
+            .rev()
+            .filter(|len| buffer.is_char_boundary(buffer.len() - len))
+            .find(|len| {
+                let suffix = &buffer[buffer.len() - len..];
+                self.stops.iter().any(|stop| stop.starts_with(suffix))
+            })
+            .unwrap_or(0)
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::State;
+use tokio::sync::{mpsc, Notify};
+
+use crate::error::CommandError;
+
+/// Tracks every in-flight stream so it can be cancelled from outside the
+/// command that started it.
+#[derive(Default)]
+pub struct StreamRegistry {
+    streams: Mutex<HashMap<String, StreamEntry>>,
+    next_id: AtomicU64,
+    idle: Notify,
+}
+
+struct StreamEntry {
+    cancel: Arc<Notify>,
+    /// Signalled when the machine is about to sleep.
+    suspended: Arc<Notify>,
+    /// Signalled when the machine wakes from sleep.
+    resumed: Arc<Notify>,
+    session_id: Option<String>,
+    /// Lines for the handler's stdin, for streams that accept input.
+    input: Option<mpsc::UnboundedSender<String>>,
+    /// Process id of the handler, once it has been spawned.
+    pid: Option<u32>,
+}
+
+impl StreamRegistry {
+    /// Register a new operation under the caller's `request_id`, or a
+    /// generated one, optionally tied to a chat session. It stays registered
+    /// until the returned guard is dropped.
+    pub fn register(
+        &self,
+        request_id: Option<&str>,
+        session_id: Option<&str>,
+    ) -> Result<StreamGuard<'_>, String> {
+        let mut streams = self.streams.lock().unwrap();
+        let id = match request_id {
+            Some(id) if streams.contains_key(id) => {
+                return Err(format!("Request id {:?} is already in use", id));
+            }
+            Some(id) => id.to_string(),
+            None => format!("stream-{}", self.next_id.fetch_add(1, Ordering::Relaxed)),
+        };
+        let cancel = Arc::new(Notify::new());
+        let suspended = Arc::new(Notify::new());
+        let resumed = Arc::new(Notify::new());
+        streams.insert(
+            id.clone(),
+            StreamEntry {
+                cancel: cancel.clone(),
+                suspended: suspended.clone(),
+                resumed: resumed.clone(),
+                session_id: session_id.map(str::to_string),
+                input: None,
+                pid: None,
+            },
+        );
+
+        Ok(StreamGuard {
+            registry: self,
+            id,
+            cancel,
+            suspended,
+            resumed,
+        })
+    }
+
+    /// Signal the operation registered as `id`. Returns whether it was found.
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.streams.lock().unwrap().get(id) {
+            Some(entry) => {
+                entry.cancel.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// How many streams are registered.
+    pub fn len(&self) -> usize {
+        self.streams.lock().unwrap().len()
+    }
+
+    /// Ids of the registered streams, sorted.
+    pub fn ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.streams.lock().unwrap().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Signal every registered stream to stop. Returns how many were signalled.
+    pub fn cancel_all(&self) -> usize {
+        let streams = self.streams.lock().unwrap();
+        for entry in streams.values() {
+            entry.cancel.notify_one();
+        }
+        streams.len()
+    }
+
+    /// Tell every registered stream the machine is about to sleep. Returns
+    /// how many were told.
+    pub fn suspend_all(&self) -> usize {
+        let streams = self.streams.lock().unwrap();
+        for entry in streams.values() {
+            entry.suspended.notify_one();
+        }
+        streams.len()
+    }
+
+    /// Tell every registered stream the machine just woke from sleep.
+    /// Returns how many were told.
+    pub fn wake_all(&self) -> usize {
+        let streams = self.streams.lock().unwrap();
+        for entry in streams.values() {
+            entry.resumed.notify_one();
+        }
+        streams.len()
+    }
+
+    /// Queue a line for the stdin of the stream registered as `id`.
+    pub fn send_input(&self, id: &str, line: String) -> Result<(), String> {
+        let streams = self.streams.lock().unwrap();
+        let entry = streams
+            .get(id)
+            .ok_or_else(|| format!("No running stream {:?}", id))?;
+        entry
+            .input
+            .as_ref()
+            .ok_or_else(|| format!("Stream {:?} does not accept input", id))?
+            .send(line)
+            .map_err(|_| format!("Stream {:?} has stopped reading input", id))
+    }
+
+    /// Signal every stream belonging to `session_id`. Returns how many were
+    /// signalled.
+    pub fn cancel_session(&self, session_id: &str) -> usize {
+        let streams = self.streams.lock().unwrap();
+        let mut cancelled = 0;
+        for entry in streams.values() {
+            if entry.session_id.as_deref() == Some(session_id) {
+                entry.cancel.notify_one();
+                cancelled += 1;
+            }
+        }
+        cancelled
+    }
+
+    /// Handler processes of the streams belonging to `session_id`.
+    pub fn session_pids(&self, session_id: &str) -> Vec<u32> {
+        self.streams
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|entry| entry.session_id.as_deref() == Some(session_id))
+            .filter_map(|entry| entry.pid)
+            .collect()
+    }
+
+    /// Wait until no streams are registered, or the timeout elapses.
+    /// Returns `true` if the registry drained in time.
+    pub async fn wait_idle(&self, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, async {
+            loop {
+                let idle = self.idle.notified();
+                if self.streams.lock().unwrap().is_empty() {
+                    return;
+                }
+                idle.await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+
+    fn remove(&self, id: &str) {
+        let mut streams = self.streams.lock().unwrap();
+        streams.remove(id);
+        if streams.is_empty() {
+            self.idle.notify_waiters();
+        }
+    }
+}
+
+/// Keeps a stream registered for as long as it is alive.
+pub struct StreamGuard<'a> {
+    registry: &'a StreamRegistry,
+    id: String,
+    cancel: Arc<Notify>,
+    suspended: Arc<Notify>,
+    resumed: Arc<Notify>,
+}
+
+impl StreamGuard<'_> {
+    /// The id the stream is registered under.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Resolves once the stream has been asked to cancel.
+    pub async fn cancelled(&self) {
+        self.cancel.notified().await
+    }
+
+    /// Resolves once the machine is about to sleep while the stream runs.
+    pub async fn suspended(&self) {
+        self.suspended.notified().await
+    }
+
+    /// Resolves once the machine has woken from sleep while the stream ran.
+    pub async fn resumed(&self) {
+        self.resumed.notified().await
+    }
+
+    /// Ask the stream to cancel, as `cancel_python_stream` would.
+    pub fn cancel(&self) {
+        self.cancel.notify_one();
+    }
+
+    /// Start accepting lines for the handler's stdin through `send_input`.
+    pub fn accept_input(&self) -> mpsc::UnboundedReceiver<String> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        if let Some(entry) = self.registry.streams.lock().unwrap().get_mut(&self.id) {
+            entry.input = Some(sender);
+        }
+        receiver
+    }
+
+    /// Record the handler process behind the stream.
+    pub fn set_pid(&self, pid: u32) {
+        if let Some(entry) = self.registry.streams.lock().unwrap().get_mut(&self.id) {
+            entry.pid = Some(pid);
+        }
+    }
+}
+
+impl Drop for StreamGuard<'_> {
+    fn drop(&mut self) {
+        self.registry.remove(&self.id);
+    }
+}
+
+/// Cancel one stream or `send_to_python` call by the `request_id` it was
+/// started with. Returns `false` if nothing is running under that id.
+#[tauri::command]
+pub fn cancel_python_stream(registry: State<'_, StreamRegistry>, request_id: String) -> bool {
+    registry.cancel(&request_id)
+}
+
+/// Cancel everything in flight for a chat session, e.g. when its tab is
+/// closed. Each stream reports its own `cancelled` chunk as it winds down.
+#[tauri::command]
+pub fn cancel_session(registry: State<'_, StreamRegistry>, session_id: String) -> usize {
+    let cancelled = registry.cancel_session(&session_id);
+    tracing::info!(session_id, cancelled, "session cancelled");
+    cancelled
+}
+
+/// Hand a tool's result back to the stream that requested it with a
+/// `tool_call` chunk, so generation can continue. The stream must have been
+/// started with `interactive`.
+#[tauri::command]
+pub fn submit_tool_result(
+    registry: State<'_, StreamRegistry>,
+    stream_id: String,
+    tool_call_id: String,
+    result: serde_json::Value,
+) -> Result<(), CommandError> {
+    let request = serde_json::json!({
+        "type": "tool_result",
+        "tool_call_id": tool_call_id,
+        "result": result,
+    });
+    let mut line = serde_json::to_string(&request).map_err(|e| e.to_string())?;
+    line.push('\n');
+    Ok(registry.send_input(&stream_id, line)?)
+}
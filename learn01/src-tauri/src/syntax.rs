@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use tauri::{AppHandle, State};
+use tokio::process::{Child, Command as AsyncCommand};
+
+use crate::config::ConfigState;
+use crate::error::CommandError;
+use crate::python;
+
+/// Compiling never runs the script, so this only guards against a broken
+/// interpreter.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Compile the script without writing bytecode and report the first syntax
+/// error as JSON.
+const CHECK_SCRIPT: &str = r#"
+import json, sys
+path = sys.argv[1]
+with open(path, "rb") as f:
+    source = f.read()
+try:
+    compile(source, path, "exec")
+except SyntaxError as e:
+    print(json.dumps({"line": e.lineno, "column": e.offset, "message": e.msg}))
+"#;
+
+/// Where and why a script fails to compile.
+#[derive(Serialize, Deserialize)]
+pub struct SyntaxIssue {
+    line: Option<u32>,
+    column: Option<u32>,
+    message: String,
+}
+
+/// Check a handler script for syntax errors without running it. Returns
+/// `None` when it compiles.
+#[tauri::command]
+pub async fn check_script_syntax(
+    app: AppHandle,
+    config: State<'_, ConfigState>,
+    script_path: PathBuf,
+) -> Result<Option<SyntaxIssue>, CommandError> {
+    if !script_path.is_file() {
+        return Err(format!("Script not found at: {:?}", script_path).into());
+    }
+
+    let config = config.get();
+    let mut command = checker(python::command(&app, &config)?, &script_path);
+    let child = python::spawn(&app, &config, &mut command)
+        .map_err(|e| format!("Failed to execute python: {}", e))?;
+    report(child).await
+}
+
+/// `python` set up to compile `script_path`.
+fn checker(python: Command, script_path: &Path) -> AsyncCommand {
+    let mut command = AsyncCommand::from(python);
+    command
+        .arg("-c")
+        .arg(CHECK_SCRIPT)
+        .arg(script_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    command
+}
+
+/// What the running checker finds.
+async fn report(child: Child) -> Result<Option<SyntaxIssue>, CommandError> {
+    let output = tokio::time::timeout(CHECK_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| "Syntax check timed out".to_string())?
+        .map_err(|e| format!("Failed to wait for python: {}", e))?;
+
+    if !output.status.success() {
+        return Err(CommandError::PythonError {
+            message: "Syntax check failed".to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            exit_code: output.status.code(),
+        });
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.trim() {
+        "" => Ok(None),
+        report => Ok(Some(serde_json::from_str(report).map_err(|e| {
+            format!("Failed to parse syntax check output: {}", e)
+        })?)),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    async fn check(source: &str) -> Option<SyntaxIssue> {
+        let path = std::env::temp_dir().join(format!("syntax-{}.py", uuid::Uuid::new_v4()));
+        std::fs::write(&path, source).unwrap();
+        let child = checker(Command::new("python3"), &path).spawn().unwrap();
+        let issue = report(child).await;
+        std::fs::remove_file(&path).unwrap();
+        issue.unwrap()
+    }
+
+    #[tokio::test]
+    async fn a_valid_script_has_no_issue() {
+        assert!(check("x = 1\nif x:\n    pass\n").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn a_broken_script_reports_where() {
+        let issue = check("x = 1\nif x\n    pass\n").await.unwrap();
+        assert_eq!((issue.line, issue.column), (Some(2), Some(5)));
+    }
+}
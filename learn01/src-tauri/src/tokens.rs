@@ -0,0 +1,107 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+use tiktoken_rs::CoreBPE;
+
+use crate::error::CommandError;
+
+/// Counts are cheap to keep but messages are unbounded; start over past this.
+const MAX_CACHED_COUNTS: usize = 4096;
+
+/// Loaded encoders and past counts, shared by every `count_tokens` call.
+#[derive(Default)]
+pub struct TokenCounter {
+    encoders: Mutex<HashMap<Tokenizer, Arc<CoreBPE>>>,
+    /// Keyed by a hash of the tokenizer and the message.
+    counts: Mutex<HashMap<u64, usize>>,
+}
+
+impl TokenCounter {
+    fn cached_count(&self, key: u64) -> Option<usize> {
+        self.counts.lock().unwrap().get(&key).copied()
+    }
+
+    /// Loaded encoders and cached counts.
+    pub fn cache_sizes(&self) -> (usize, usize) {
+        (
+            self.encoders.lock().unwrap().len(),
+            self.counts.lock().unwrap().len(),
+        )
+    }
+
+    pub fn clear(&self) {
+        self.encoders.lock().unwrap().clear();
+        self.counts.lock().unwrap().clear();
+    }
+
+    fn store_count(&self, key: u64, count: usize) {
+        let mut counts = self.counts.lock().unwrap();
+        if counts.len() >= MAX_CACHED_COUNTS {
+            counts.clear();
+        }
+        counts.insert(key, count);
+    }
+
+    /// The encoder `model` uses, loading it on first use.
+    pub async fn encoder_for_model(&self, model: &str) -> Result<Arc<CoreBPE>, CommandError> {
+        let tokenizer = get_tokenizer(model).ok_or_else(|| CommandError::TokenizerUnavailable {
+            model: model.to_string(),
+        })?;
+        Ok(self.encoder(tokenizer).await?)
+    }
+
+    /// The encoder for `tokenizer`, loading it on first use.
+    async fn encoder(&self, tokenizer: Tokenizer) -> Result<Arc<CoreBPE>, String> {
+        if let Some(encoder) = self.encoders.lock().unwrap().get(&tokenizer) {
+            return Ok(encoder.clone());
+        }
+
+        // Building the BPE ranks takes a while; keep it off the async runtime
+        let encoder =
+            tokio::task::spawn_blocking(move || tiktoken_rs::get_bpe_from_tokenizer(tokenizer))
+                .await
+                .map_err(|e| format!("Tokenizer task failed: {}", e))?
+                .map_err(|e| format!("Failed to load tokenizer: {}", e))?;
+
+        let encoder = Arc::new(encoder);
+        self.encoders
+            .lock()
+            .unwrap()
+            .insert(tokenizer, encoder.clone());
+        Ok(encoder)
+    }
+}
+
+fn cache_key(tokenizer: Tokenizer, message: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    tokenizer.hash(&mut hasher);
+    message.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Number of tokens `message` takes up for `model`, so the UI can warn before
+/// the context window is exceeded.
+#[tauri::command]
+pub async fn count_tokens(
+    counter: tauri::State<'_, TokenCounter>,
+    message: String,
+    model: String,
+) -> Result<usize, CommandError> {
+    let tokenizer = get_tokenizer(&model).ok_or(CommandError::TokenizerUnavailable { model })?;
+
+    let key = cache_key(tokenizer, &message);
+    if let Some(count) = counter.cached_count(key) {
+        return Ok(count);
+    }
+
+    let encoder = counter.encoder(tokenizer).await?;
+    let count =
+        tokio::task::spawn_blocking(move || encoder.encode_with_special_tokens(&message).len())
+            .await
+            .map_err(|e| format!("Tokenizer task failed: {}", e))?;
+
+    counter.store_count(key, count);
+    Ok(count)
+}
@@ -0,0 +1,78 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::StreamChunk;
+
+/// How often a transcript is flushed to disk, unless it's `durable`.
+const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Writes every chunk a stream emits to an `.ndjson` file before it is
+/// emitted, so a crash leaves the transcript complete up to what the
+/// frontend saw. Writes and syncs happen on the blocking pool, so a slow
+/// disk stalls only the stream writing to it.
+pub struct Transcript {
+    file: Arc<File>,
+    /// Flush to disk after every chunk rather than every `SYNC_INTERVAL`.
+    durable: bool,
+    synced: Instant,
+}
+
+impl Transcript {
+    pub fn create(path: &Path, durable: bool) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create transcript {:?}: {}", path, e))?;
+        Ok(Self {
+            file: Arc::new(file),
+            durable,
+            synced: Instant::now(),
+        })
+    }
+
+    /// Append a chunk that's about to be emitted. Until this succeeds the
+    /// chunk mustn't be emitted.
+    pub async fn write(&mut self, chunk: &StreamChunk) -> Result<(), String> {
+        let mut json = serde_json::to_string(chunk).map_err(|e| e.to_string())?;
+        json.push('\n');
+        let sync = self.durable || self.synced.elapsed() >= SYNC_INTERVAL;
+        let file = self.file.clone();
+        tokio::task::spawn_blocking(move || {
+            (&*file)
+                .write_all(json.as_bytes())
+                .map_err(|e| format!("Failed to write transcript: {}", e))?;
+            if sync {
+                sync_data(&file)?;
+            }
+            Ok::<_, String>(())
+        })
+        .await
+        .map_err(|e| format!("Failed to write transcript: {}", e))??;
+        if sync {
+            self.synced = Instant::now();
+        }
+        Ok(())
+    }
+}
+
+fn sync_data(file: &File) -> Result<(), String> {
+    file.sync_data()
+        .map_err(|e| format!("Failed to sync transcript: {}", e))
+}
+
+impl Drop for Transcript {
+    fn drop(&mut self) {
+        let file = self.file.clone();
+        let sync = move || {
+            if let Err(e) = sync_data(&file) {
+                tracing::warn!("{}", e);
+            }
+        };
+        // Off the runtime's threads, as for writes, where there is one
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(sync)),
+            Err(_) => sync(),
+        }
+    }
+}
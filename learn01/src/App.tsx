@@ -13,6 +13,7 @@ interface ChatResponse {
   success: boolean;
   message?: string;
   error?: string;
+  warnings?: string;
 }
 
 interface StreamChunk {
@@ -22,6 +23,43 @@ interface StreamChunk {
   error?: string;
 }
 
+// Structured error returned by the Rust commands
+type CommandError =
+  | { kind: "message_too_long"; limit: number; actual: number }
+  | { kind: "python_error"; message: string; stderr: string }
+  | { kind: "model_load_failed"; model_id: string; reason: string; message: string }
+  | { kind: "cancelled" }
+  | { kind: "timeout"; phase: "first_chunk" | "deadline" }
+  | { kind: "tokenizer_unavailable"; model: string }
+  | { kind: "failed"; message: string };
+
+const formatCommandError = (error: unknown): string => {
+  if (typeof error !== "object" || error === null || !("kind" in error)) {
+    return String(error);
+  }
+  const commandError = error as CommandError;
+  switch (commandError.kind) {
+    case "message_too_long":
+      return `Message is too long: ${commandError.actual} characters (limit is ${commandError.limit})`;
+    case "python_error":
+      return commandError.stderr
+        ? `${commandError.message}: ${commandError.stderr.trim()}`
+        : commandError.message;
+    case "model_load_failed":
+      return `Failed to load model '${commandError.model_id}': ${commandError.message}`;
+    case "cancelled":
+      return "Cancelled";
+    case "timeout":
+      return commandError.phase === "first_chunk"
+        ? "The handler didn't start responding in time"
+        : "The stream's deadline passed";
+    case "tokenizer_unavailable":
+      return `No tokenizer available for model '${commandError.model}'`;
+    case "failed":
+      return commandError.message;
+  }
+};
+
 interface SystemStatus {
   pythonAvailable: boolean;
   pythonInfo?: string;
@@ -257,7 +295,7 @@ function App() {
 
         const errorMessage: ChatMessage = {
           role: "error",
-          content: `Streaming failed: ${error}`,
+          content: `Streaming failed: ${formatCommandError(error)}`,
           timestamp: new Date(),
         };
         setMessages((prev) => [...prev, errorMessage]);
@@ -318,7 +356,7 @@ function App() {
         clearTimeout(timeoutId);
         const errorMessage: ChatMessage = {
           role: "error",
-          content: `Failed to communicate with Python: ${error}`,
+          content: `Failed to communicate with Python: ${formatCommandError(error)}`,
           timestamp: new Date(),
         };
         setMessages((prev) => [...prev, errorMessage]);